@@ -0,0 +1,169 @@
+use {
+    camino::Utf8PathBuf,
+    cargo_metadata::{diagnostic, Message},
+    std::fmt,
+};
+
+/// Something that happened during a build.
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// A unit was compiled.
+    Artifact(Artifact),
+    /// The compiler emitted a diagnostic.
+    Diagnostic(Diagnostic),
+    /// A build script was run.
+    BuildScript(BuildScript),
+    /// Cargo is done building.
+    BuildFinished { success: bool },
+}
+
+/// A compiled unit.
+#[derive(Clone, Debug)]
+pub struct Artifact {
+    pub package_id: String,
+    /// Name of the cargo target, such as the binary name.
+    pub target: String,
+    /// Kinds of the cargo target, such as `bin` or `lib`.
+    pub kinds: Vec<String>,
+    /// Every file produced.
+    pub filenames: Vec<Utf8PathBuf>,
+    /// The executable, for binaries.
+    pub executable: Option<Utf8PathBuf>,
+    /// Whether the unit was already up to date.
+    pub fresh: bool,
+}
+
+/// A compiler diagnostic.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub package_id: String,
+    pub level: Level,
+    pub message: String,
+    pub spans: Vec<Span>,
+    /// The diagnostic as rustc would print it.
+    pub rendered: Option<String>,
+}
+
+/// Severity of a diagnostic.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Level {
+    /// An internal compiler error.
+    Ice,
+    Error,
+    Warning,
+    FailureNote,
+    Note,
+    Help,
+}
+
+/// A source location a diagnostic refers to.
+#[derive(Clone, Debug)]
+pub struct Span {
+    pub file_name: String,
+    pub line_start: usize,
+    pub line_end: usize,
+    pub column_start: usize,
+    pub column_end: usize,
+    /// Whether this is the location the diagnostic is about.
+    pub is_primary: bool,
+    pub label: Option<String>,
+}
+
+/// Output of a build script.
+#[derive(Clone, Debug)]
+pub struct BuildScript {
+    pub package_id: String,
+    pub linked_libs: Vec<Utf8PathBuf>,
+    pub linked_paths: Vec<Utf8PathBuf>,
+    pub cfgs: Vec<String>,
+    pub env: Vec<(String, String)>,
+    pub out_dir: Utf8PathBuf,
+}
+
+impl Event {
+    /// Convert a cargo message, messages of no interest yield `None`.
+    pub(crate) fn from_message(message: Message) -> Option<Self> {
+        let event = match message {
+            Message::CompilerArtifact(artifact) => Self::Artifact(Artifact {
+                package_id: artifact.package_id.repr,
+                target: artifact.target.name,
+                kinds: artifact.target.kind,
+                filenames: artifact.filenames,
+                executable: artifact.executable,
+                fresh: artifact.fresh,
+            }),
+            Message::CompilerMessage(message) => {
+                let diagnostic = message.message;
+
+                Self::Diagnostic(Diagnostic {
+                    package_id: message.package_id.repr,
+                    level: diagnostic.level.into(),
+                    message: diagnostic.message,
+                    spans: diagnostic.spans.into_iter().map(Span::from).collect(),
+                    rendered: diagnostic.rendered,
+                })
+            }
+            Message::BuildScriptExecuted(script) => Self::BuildScript(BuildScript {
+                package_id: script.package_id.repr,
+                linked_libs: script.linked_libs,
+                linked_paths: script.linked_paths,
+                cfgs: script.cfgs,
+                env: script.env,
+                out_dir: script.out_dir,
+            }),
+            Message::BuildFinished(finished) => Self::BuildFinished {
+                success: finished.success,
+            },
+            _ => return None,
+        };
+
+        Some(event)
+    }
+}
+
+impl Diagnostic {
+    /// Whether the diagnostic fails the build.
+    pub fn is_error(&self) -> bool {
+        matches!(self.level, Level::Error | Level::Ice)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.rendered {
+            Some(rendered) => fmt.write_str(rendered),
+            None => fmt.write_str(&self.message),
+        }
+    }
+}
+
+impl From<diagnostic::DiagnosticLevel> for Level {
+    fn from(level: diagnostic::DiagnosticLevel) -> Self {
+        use diagnostic::DiagnosticLevel;
+
+        match level {
+            DiagnosticLevel::Ice => Self::Ice,
+            DiagnosticLevel::Error => Self::Error,
+            DiagnosticLevel::Warning => Self::Warning,
+            DiagnosticLevel::FailureNote => Self::FailureNote,
+            DiagnosticLevel::Note => Self::Note,
+            DiagnosticLevel::Help => Self::Help,
+            // Levels added by newer compilers are informational so far.
+            _ => Self::Note,
+        }
+    }
+}
+
+impl From<diagnostic::DiagnosticSpan> for Span {
+    fn from(span: diagnostic::DiagnosticSpan) -> Self {
+        Self {
+            file_name: span.file_name,
+            line_start: span.line_start,
+            line_end: span.line_end,
+            column_start: span.column_start,
+            column_end: span.column_end,
+            is_primary: span.is_primary,
+            label: span.label,
+        }
+    }
+}
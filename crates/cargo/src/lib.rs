@@ -1,21 +1,26 @@
+pub use event::{Artifact, BuildScript, Diagnostic, Event, Level, Span};
+
 use {
     camino::{Utf8Path, Utf8PathBuf},
-    cargo_metadata::Message,
+    cargo_metadata::{Message, Metadata},
+    jobserver::Client,
     milk_target::Target,
-    serde::Deserialize,
     std::{
         collections::BTreeSet,
         fs,
         io::{self, Cursor},
         os::unix::fs::PermissionsExt,
-        process::Stdio,
+        process::{ExitStatus, Stdio},
     },
     tokio::{
-        io::{AsyncBufReadExt, BufReader, Lines},
+        io::{AsyncBufReadExt, AsyncReadExt, BufReader, Lines},
         process::{ChildStderr, ChildStdout, Command},
+        task::JoinHandle,
     },
 };
 
+mod event;
+
 /// A `cargo` invocation context.
 pub struct Cargo {
     cargo_path: Utf8PathBuf,
@@ -27,18 +32,38 @@ pub struct Build {
     workspace_path: Utf8PathBuf,
     features: BTreeSet<String>,
     target: Target,
+    zig_path: Option<Utf8PathBuf>,
+    jobserver: Option<Client>,
+    toolchain: Option<String>,
+    zigbuild: bool,
+    profile: String,
+    default_features: bool,
+    locked: bool,
+    frozen: bool,
+    offline: bool,
+    bins: BTreeSet<String>,
+    rustflags: Vec<String>,
+    envs: Vec<(String, String)>,
 }
 
 /// A `cargo build` child process.
 pub struct Child {
     cargo_path: Utf8PathBuf,
     workspace_path: Utf8PathBuf,
-    features: String,
+    toolchain: Option<String>,
+    /// Arguments affecting dependency resolution, shared with `cargo metadata`.
+    resolve_args: Vec<String>,
     triple: String,
+    /// The command line, as shown by `Debug`.
+    command: String,
     child: tokio::process::Child,
     stdout: Lines<BufReader<ChildStdout>>,
+    stderr: JoinHandle<io::Result<String>>,
     completed: usize,
     total: Option<usize>,
+    /// Where the unit count of a successful build is kept for the next one.
+    units_path: Option<Utf8PathBuf>,
+    errors: Vec<String>,
 }
 
 /// Child process status.
@@ -47,9 +72,13 @@ pub struct Status {
     pub total: usize,
 }
 
-#[derive(Debug, Deserialize)]
-struct BuildPlan {
-    invocations: Vec<serde_json::Value>,
+/// Outcome of a `cargo build`.
+pub struct Finished {
+    pub status: ExitStatus,
+    /// Rendered compiler errors.
+    pub errors: Vec<String>,
+    /// Cargo's own output, such as manifest or resolution errors.
+    pub stderr: String,
 }
 
 impl Cargo {
@@ -60,7 +89,8 @@ impl Cargo {
     where
         P: AsRef<Utf8Path>,
     {
-        let cargo_path = cargo_path.as_ref().canonicalize_utf8()?;
+        // Not canonicalized, rustup proxies pick the tool by the name they are run as.
+        let cargo_path = cargo_path.as_ref().to_path_buf();
         let metadata = fs::metadata(&cargo_path)?;
 
         // Is not a file.
@@ -86,6 +116,19 @@ impl Cargo {
             workspace_path: workspace_path.into(),
             features: BTreeSet::new(),
             target: Target::HOST,
+            zig_path: None,
+            jobserver: None,
+            // Ignores the `rust-toolchain` file in projects.
+            toolchain: Some(String::from("nightly")),
+            zigbuild: true,
+            profile: String::from("release"),
+            default_features: false,
+            locked: false,
+            frozen: false,
+            offline: false,
+            bins: BTreeSet::new(),
+            rustflags: Vec::new(),
+            envs: Vec::new(),
         }
     }
 }
@@ -119,6 +162,142 @@ impl Build {
         self
     }
 
+    /// Set the zig binary used by `cargo zigbuild`.
+    ///
+    /// Otherwise it is searched for in `PATH`.
+    pub fn zig<P>(mut self, zig_path: P) -> Self
+    where
+        P: Into<Utf8PathBuf>,
+    {
+        self.zig_path = Some(zig_path.into());
+        self
+    }
+
+    /// Share a jobserver with cargo.
+    ///
+    /// Cargo then takes a token for every job beyond its first.
+    pub fn jobserver(mut self, jobserver: Client) -> Self {
+        self.jobserver = Some(jobserver);
+        self
+    }
+
+    /// Set the rustup toolchain, such as `stable`.
+    ///
+    /// Defaults to `nightly`. `None` uses whichever toolchain cargo picks.
+    pub fn toolchain<S>(mut self, toolchain: Option<S>) -> Self
+    where
+        S: Into<String>,
+    {
+        self.toolchain = toolchain.map(Into::into);
+        self
+    }
+
+    /// Build with `cargo zigbuild`, the default, or plain `cargo build`.
+    pub fn zigbuild(mut self, zigbuild: bool) -> Self {
+        self.zigbuild = zigbuild;
+        self
+    }
+
+    /// Set the profile to build with, defaults to `release`.
+    pub fn profile<S>(mut self, profile: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.profile = profile.into();
+        self
+    }
+
+    /// Enable the `default` feature, disabled by default.
+    pub fn default_features(mut self, default_features: bool) -> Self {
+        self.default_features = default_features;
+        self
+    }
+
+    /// Require `Cargo.lock` to be up to date.
+    pub fn locked(mut self, locked: bool) -> Self {
+        self.locked = locked;
+        self
+    }
+
+    /// Require `Cargo.lock` and the dependency cache to be up to date.
+    pub fn frozen(mut self, frozen: bool) -> Self {
+        self.frozen = frozen;
+        self
+    }
+
+    /// Build without accessing the network.
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Only build the binary `bin`.
+    ///
+    /// Without any, every binary of the workspace is built.
+    pub fn bin<S>(mut self, bin: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.bins.insert(bin.into());
+        self
+    }
+
+    /// Only build the binaries `bins`.
+    pub fn bins<I, S>(mut self, bins: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        for bin in bins {
+            self = self.bin(bin);
+        }
+
+        self
+    }
+
+    /// Append flags passed to every rustc invocation.
+    pub fn rustflags<I, S>(mut self, rustflags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.rustflags.extend(rustflags.into_iter().map(Into::into));
+        self
+    }
+
+    /// Set an environment variable for cargo.
+    pub fn env<K, V>(mut self, key: K, value: V) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.envs.push((key.into(), value.into()));
+        self
+    }
+
+    /// The `cargo fetch` that downloads the dependencies of the build, so
+    /// it can be built offline.
+    pub fn fetch(self) -> std::process::Command {
+        let mut command = std::process::Command::new(&self.cargo_path);
+
+        command.envs(self.envs).current_dir(&self.workspace_path);
+
+        if let Some(toolchain) = &self.toolchain {
+            command.arg(format!("+{toolchain}"));
+        }
+
+        command
+            .arg("fetch")
+            .arg(format!("--target={}", self.target.rust_triple()));
+
+        if self.locked {
+            command.arg("--locked");
+        }
+
+        command.stdin(Stdio::null());
+        command
+    }
+
     /// Start the build.
     pub fn spawn(self) -> io::Result<Child> {
         let Self {
@@ -126,102 +305,281 @@ impl Build {
             workspace_path,
             features,
             target,
+            zig_path,
+            jobserver,
+            toolchain,
+            zigbuild,
+            profile,
+            default_features,
+            locked,
+            frozen,
+            offline,
+            bins,
+            rustflags,
+            envs,
         } = self;
 
         let features = features.into_iter().collect::<Vec<_>>().join(",");
         let triple = target.rust_triple().into();
 
-        let mut child = Command::new(&cargo_path)
-            .current_dir(&workspace_path)
-            // Force a toolchain to ignore the `rust-toolchain` file in projects.
-            .arg("+nightly")
+        let mut resolve_args = Vec::new();
+
+        if !default_features {
+            resolve_args.push(String::from("--no-default-features"));
+        }
+
+        resolve_args.push(format!("--features={features}"));
+
+        for (enabled, flag) in [
+            (locked, "--locked"),
+            (frozen, "--frozen"),
+            (offline, "--offline"),
+        ] {
+            if enabled {
+                resolve_args.push(String::from(flag));
+            }
+        }
+
+        let mut command = std::process::Command::new(&cargo_path);
+
+        command.envs(envs);
+
+        if !rustflags.is_empty() {
+            // Unlike `RUSTFLAGS`, allows spaces within flags.
+            command.env("CARGO_ENCODED_RUSTFLAGS", rustflags.join("\x1f"));
+        }
+
+        if let Some(zig_path) = zig_path {
+            command.env("CARGO_ZIGBUILD_ZIG_PATH", zig_path);
+        }
+
+        if let Some(jobserver) = jobserver {
+            jobserver.configure(&mut command);
+        }
+
+        command.current_dir(&workspace_path);
+
+        if let Some(toolchain) = &toolchain {
+            command.arg(format!("+{toolchain}"));
+        }
+
+        command
             // Zig toolchain, my beloved.
-            .arg("zigbuild")
-            // Parse JSON messages.
-            .arg("--message-format=json-render-diagnostics")
-            .arg("--no-default-features")
-            .arg("--release")
-            .arg(format!("--features={features}"))
+            .arg(if zigbuild { "zigbuild" } else { "build" })
+            // Parse JSON messages, including compiler diagnostics.
+            .arg("--message-format=json")
+            .arg(format!("--profile={profile}"))
+            .args(&resolve_args)
             .arg(format!("--target={triple}"))
+            .args(bins.iter().map(|bin| format!("--bin={bin}")))
             .stdin(Stdio::null())
             .stdout(Stdio::piped())
-            .stderr(Stdio::null())
-            .spawn()?;
+            .stderr(Stdio::piped());
+
+        let description = format!("{command:?}");
+        let mut child = Command::from(command).spawn()?;
 
         let stdout = child.stdout.take().ok_or_else(missing_stdio)?;
         let stdout = BufReader::new(stdout).lines();
 
+        // Drained concurrently, so a full pipe cannot stall cargo.
+        let stderr = child.stderr.take().ok_or_else(missing_stdio)?;
+        let stderr = tokio::spawn(read_to_string(stderr));
+
         Ok(Child {
             cargo_path,
             workspace_path,
-            features,
+            toolchain,
+            resolve_args,
             triple,
+            command: description,
             child,
             stdout,
+            stderr,
             completed: 0,
             total: None,
+            units_path: None,
+            errors: Vec::new(),
         })
     }
 }
 
 impl Child {
-    pub async fn process(&mut self) -> io::Result<Option<Status>> {
-        // Unfortunate, but necessary.
+    /// Wait for the next event.
+    ///
+    /// Returns `None` once cargo closes its output.
+    pub async fn process(&mut self) -> io::Result<Option<Event>> {
         if self.total.is_none() {
-            let Self {
-                cargo_path,
-                workspace_path,
-                features,
-                triple,
-                ..
-            } = self;
-
-            let output = Command::new(cargo_path)
-                .current_dir(&workspace_path)
-                .arg("+nightly")
-                .arg("build")
-                .arg("--build-plan")
-                .arg("--no-default-features")
-                .arg("--release")
-                .arg("-Zunstable-options")
-                .arg(format!("--features={features}"))
-                .arg(format!("--target={triple}"))
-                .stdin(Stdio::null())
-                .stdout(Stdio::piped())
-                .stderr(Stdio::null())
-                .output()
-                .await?;
-
-            let build_plan: BuildPlan = serde_json::from_slice(&output.stdout)
-                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
-
-            self.total = Some(build_plan.invocations.len());
-        }
-
-        let Some(line) = self.stdout.next_line().await? else {
-            return Ok(None);
+            self.total = Some(self.estimate_units().await);
+        }
+
+        loop {
+            let Some(line) = self.stdout.next_line().await? else {
+                return Ok(None);
+            };
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let message = Message::parse_stream(Cursor::new(line.as_bytes()))
+                .next()
+                .ok_or_else(invalid_message)??;
+
+            let Some(event) = Event::from_message(message) else {
+                continue;
+            };
+
+            match &event {
+                Event::Diagnostic(diagnostic) if diagnostic.is_error() => {
+                    self.errors.push(diagnostic.to_string());
+                }
+                // Every finished unit produces one of these.
+                Event::Artifact(_) | Event::BuildScript(_) => self.completed += 1,
+                _ => {}
+            }
+
+            return Ok(Some(event));
+        }
+    }
+
+    /// The command line cargo was started with.
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    /// Units built so far, out of the estimated total.
+    pub fn status(&self) -> Status {
+        // The estimate may be short, never report more than all work done.
+        let total = self.total.unwrap_or_default().max(self.completed);
+
+        Status {
+            completed: self.completed,
+            total,
+        }
+    }
+
+    /// Estimate the number of units cargo is about to build.
+    ///
+    /// The count of the last successful build is reused when there is one.
+    /// Otherwise it is derived from `cargo metadata`. Zero means unknown.
+    async fn estimate_units(&mut self) -> usize {
+        let Ok(metadata) = self.metadata().await else {
+            return 0;
         };
 
-        let messsge = Message::parse_stream(Cursor::new(line.as_bytes()))
-            .next()
-            .ok_or_else(invalid_message)?;
+        let units_path = metadata
+            .target_directory
+            .join(&self.triple)
+            .join(".milk-units");
+
+        let cached = fs::read_to_string(&units_path)
+            .ok()
+            .and_then(|units| units.trim().parse().ok());
 
+        self.units_path = Some(units_path);
+
+        cached.unwrap_or_else(|| estimate_units(&metadata))
+    }
+
+    async fn metadata(&self) -> io::Result<Metadata> {
         let Self {
-            completed, total, ..
+            cargo_path,
+            workspace_path,
+            toolchain,
+            resolve_args,
+            triple,
+            ..
         } = self;
 
-        // SAFETY: Total is initialized by the check above.
-        let total = unsafe { total.unwrap_unchecked() };
+        let mut command = Command::new(cargo_path);
 
-        if *completed < total {
-            *completed += 1;
+        command.current_dir(workspace_path);
+
+        if let Some(toolchain) = toolchain {
+            command.arg(format!("+{toolchain}"));
         }
 
-        Ok(Some(Status {
-            completed: *completed,
-            total,
-        }))
+        let output = command
+            .arg("metadata")
+            .arg("--format-version=1")
+            .args(resolve_args)
+            .arg(format!("--filter-platform={triple}"))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .output()
+            .await?;
+
+        serde_json::from_slice(&output.stdout)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
     }
+
+    /// Wait for cargo to exit.
+    ///
+    /// Remaining messages are processed first.
+    pub async fn wait(mut self) -> io::Result<Finished> {
+        while self.process().await?.is_some() {}
+
+        let status = self.child.wait().await?;
+        let stderr = self.stderr.await.map_err(io::Error::other)??;
+
+        if status.success() {
+            if let Some(units_path) = &self.units_path {
+                // Only improves the next estimate.
+                let _ = fs::write(units_path, self.completed.to_string());
+            }
+        }
+
+        Ok(Finished {
+            status,
+            errors: self.errors,
+            stderr,
+        })
+    }
+}
+
+/// Estimate units from the resolved packages.
+///
+/// Each library is one unit and a build script two, one to compile it and
+/// one to run it. Binaries are only built for workspace members.
+fn estimate_units(metadata: &Metadata) -> usize {
+    let Some(resolve) = &metadata.resolve else {
+        return 0;
+    };
+
+    resolve
+        .nodes
+        .iter()
+        .map(|node| {
+            let package = &metadata[&node.id];
+            let is_member = metadata.workspace_members.contains(&node.id);
+
+            package
+                .targets
+                .iter()
+                .map(|target| {
+                    if target.is_custom_build() {
+                        2
+                    } else if target.is_bin() {
+                        usize::from(is_member)
+                    } else if target.is_example() || target.is_test() || target.is_bench() {
+                        0
+                    } else {
+                        1
+                    }
+                })
+                .sum::<usize>()
+        })
+        .sum()
+}
+
+async fn read_to_string(mut stderr: ChildStderr) -> io::Result<String> {
+    let mut buffer = Vec::new();
+
+    stderr.read_to_end(&mut buffer).await?;
+
+    Ok(String::from_utf8_lossy(&buffer).into_owned())
 }
 
 fn cargo_must_be_an_exe() -> io::Error {
@@ -1,10 +1,20 @@
-use std::io::{self, Write};
+use std::{
+    env, fmt,
+    io::{self, IsTerminal, Stdout, Write},
+    mem,
+    os::fd::AsRawFd,
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
 
 /// A single bar.
 struct Bar<'a> {
     label: &'a str,
+    phase: &'a str,
     completed: usize,
     total: usize,
+    elapsed: Option<Duration>,
+    eta: Option<Duration>,
 }
 
 /// A terminal widget to display one or more progress bars.
@@ -37,8 +47,39 @@ impl<'a> ProgressBars<'a> {
 
         self.bars.push(Bar {
             label,
+            phase: "",
             completed,
             total,
+            elapsed: None,
+            eta: None,
+        });
+
+        self
+    }
+
+    /// Add a bar for a task in `phase`.
+    ///
+    /// A `total` of zero means the amount of work is unknown, only the
+    /// elapsed time is shown.
+    #[inline]
+    pub fn add_phase(
+        mut self,
+        label: &'a str,
+        phase: &'a str,
+        completed: usize,
+        total: usize,
+        elapsed: Duration,
+        eta: Option<Duration>,
+    ) -> Self {
+        assert!(completed <= total);
+
+        self.bars.push(Bar {
+            label,
+            phase,
+            completed,
+            total,
+            elapsed: Some(elapsed),
+            eta,
         });
 
         self
@@ -67,6 +108,12 @@ impl<'a> ProgressBars<'a> {
             .max()
             .unwrap_or_default();
 
+        let phase_width = bars
+            .iter()
+            .map(|bar| bar.phase.len())
+            .max()
+            .unwrap_or_default();
+
         let completed_width = bars
             .iter()
             .map(|bar| bar.completed.checked_ilog10().unwrap_or_default())
@@ -83,18 +130,50 @@ impl<'a> ProgressBars<'a> {
 
         for Bar {
             label,
+            phase,
             completed,
             total,
+            elapsed,
+            eta,
         } in bars
         {
-            let message = format!("{completed:completed_width$} / {total}");
-            let remaining_width = terminal_width.saturating_sub(message.len() - 1);
-            let repeat = ((completed as f32) / (total as f32) * (remaining_width as f32)) as usize;
+            let mut message = Vec::new();
+
+            if total > 0 {
+                message.push(format!("{completed:completed_width$} / {total}"));
+            }
+
+            if let Some(elapsed) = elapsed {
+                message.push(format_duration(elapsed));
+            }
+
+            if let Some(eta) = eta {
+                message.push(format!("ETA {}", format_duration(eta)));
+            }
+
+            let message = message.join(" ");
+
+            let phase = if phase_width > 0 {
+                format!(" {phase:phase_width$}")
+            } else {
+                String::new()
+            };
+
+            // Leave the last column free, a full line wraps on some terminals.
+            let remaining_width =
+                terminal_width.saturating_sub(label_width + phase.len() + message.len() + 3);
+
+            let repeat = if total > 0 {
+                ((completed as f32) / (total as f32) * (remaining_width as f32)) as usize
+            } else {
+                0
+            };
+
             let bar = bar_character.repeat(repeat);
 
             write!(
                 writer,
-                "\r{label:label_width$} {bar:remaining_width$} {message}\x1b[1B"
+                "\r{label:label_width$}{phase} {bar:remaining_width$} {message}\x1b[1B"
             )?;
         }
 
@@ -106,3 +185,264 @@ impl<'a> ProgressBars<'a> {
         Ok(())
     }
 }
+
+/// Live progress of several tasks, drawn below regular output.
+///
+/// Every task goes through phases, such as fetching and building, and ends
+/// in success or failure. Bars are redrawn in place and follow the terminal
+/// width. When stdout is not a terminal, progress is written as plain lines
+/// instead.
+pub struct Progress {
+    shared: Mutex<Shared>,
+}
+
+struct Shared {
+    stdout: Stdout,
+    is_terminal: bool,
+    tasks: Vec<Task>,
+}
+
+struct Task {
+    label: String,
+    phase: String,
+    completed: usize,
+    total: usize,
+    started: Instant,
+    phase_started: Instant,
+    /// Last tenth reported in plain output.
+    reported: usize,
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        let stdout = io::stdout();
+        let is_terminal = stdout.is_terminal();
+
+        Self {
+            shared: Mutex::new(Shared {
+                stdout,
+                is_terminal,
+                tasks: Vec::new(),
+            }),
+        }
+    }
+
+    /// Move the task named `label` into `phase`, adding it if necessary.
+    pub fn phase(&self, label: &str, phase: &str) {
+        let mut shared = self.lock();
+        let task = shared.task(label);
+
+        task.phase = phase.into();
+        task.completed = 0;
+        task.total = 0;
+        task.phase_started = Instant::now();
+        task.reported = 0;
+
+        if shared.is_terminal {
+            shared.redraw(|_| Ok(()));
+        } else {
+            shared.redraw(|stdout| writeln!(stdout, "{label} {phase}"));
+        }
+    }
+
+    /// Set the progress of the current phase of the task named `label`.
+    pub fn update(&self, label: &str, completed: usize, total: usize) {
+        let mut shared = self.lock();
+        let is_terminal = shared.is_terminal;
+        let task = shared.task(label);
+        let completed = completed.min(total);
+
+        task.completed = completed;
+        task.total = total;
+
+        if is_terminal {
+            shared.redraw(|_| Ok(()));
+            return;
+        }
+
+        let tenth = (completed * 10).checked_div(total).unwrap_or_default();
+
+        if tenth > task.reported {
+            task.reported = tenth;
+
+            let phase = task.phase.clone();
+
+            shared.redraw(|stdout| writeln!(stdout, "{label} {phase} {completed} / {total}"));
+        }
+    }
+
+    /// Stop displaying the task named `label` and report how it ended.
+    pub fn finish(&self, label: &str, succeeded: bool) {
+        let mut shared = self.lock();
+
+        let Some(index) = shared.tasks.iter().position(|task| task.label == label) else {
+            return;
+        };
+
+        let task = shared.tasks.remove(index);
+        let elapsed = format_duration(task.started.elapsed());
+
+        shared.redraw(|stdout| {
+            if succeeded {
+                writeln!(stdout, " \u{2714} {label} done in {elapsed}")
+            } else if task.phase.is_empty() {
+                writeln!(stdout, " \u{2718} {label} failed after {elapsed}")
+            } else {
+                let phase = &task.phase;
+
+                writeln!(
+                    stdout,
+                    " \u{2718} {label} failed in {phase} after {elapsed}"
+                )
+            }
+        });
+    }
+
+    /// Print a line above the bars.
+    pub fn println<D: fmt::Display>(&self, line: D) {
+        self.lock().redraw(|stdout| writeln!(stdout, "{line}"));
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Shared> {
+        // Progress is cosmetic, a panic elsewhere must not stop it.
+        self.shared.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        let shared = self
+            .shared
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        shared.tasks.clear();
+        shared.redraw(|_| Ok(()));
+    }
+}
+
+impl Shared {
+    fn task(&mut self, label: &str) -> &mut Task {
+        let index = match self.tasks.iter().position(|task| task.label == label) {
+            Some(index) => index,
+            None => {
+                let now = Instant::now();
+
+                self.tasks.push(Task {
+                    label: label.into(),
+                    phase: String::new(),
+                    completed: 0,
+                    total: 0,
+                    started: now,
+                    phase_started: now,
+                    reported: 0,
+                });
+
+                self.tasks.len() - 1
+            }
+        };
+
+        &mut self.tasks[index]
+    }
+
+    /// Clear the bars, run `write` and draw the bars again.
+    ///
+    /// Write errors are ignored, a closed stdout must not fail anything.
+    fn redraw<F>(&mut self, write: F)
+    where
+        F: FnOnce(&mut dyn Write) -> io::Result<()>,
+    {
+        let _ = self.try_redraw(write);
+    }
+
+    fn try_redraw<F>(&mut self, write: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut dyn Write) -> io::Result<()>,
+    {
+        let Self {
+            stdout,
+            is_terminal,
+            tasks,
+        } = self;
+
+        let mut stdout = stdout.lock();
+
+        if !*is_terminal {
+            write(&mut stdout)?;
+
+            return stdout.flush();
+        }
+
+        // The cursor rests at the top of the bars.
+        write!(stdout, "\r\x1b[J")?;
+        write(&mut stdout)?;
+
+        if tasks.is_empty() {
+            return stdout.flush();
+        }
+
+        let bars = tasks
+            .iter()
+            .fold(ProgressBars::new(), |bars, task| {
+                bars.add_phase(
+                    &task.label,
+                    &task.phase,
+                    task.completed,
+                    task.total,
+                    task.started.elapsed(),
+                    task.eta(),
+                )
+            })
+            .terminal_width(terminal_width());
+
+        bars.render(&mut stdout)
+    }
+}
+
+impl Task {
+    /// Estimate the time left in the current phase from its throughput so far.
+    fn eta(&self) -> Option<Duration> {
+        if self.completed == 0 || self.completed >= self.total {
+            return None;
+        }
+
+        let elapsed = self.phase_started.elapsed();
+        let remaining = (self.total - self.completed) as u32;
+
+        Some(elapsed / self.completed as u32 * remaining)
+    }
+}
+
+/// Width of the terminal attached to stdout.
+///
+/// Queried on every draw, so a resized terminal is picked up.
+pub fn terminal_width() -> usize {
+    // SAFETY: `winsize` is plain data and only read if the call succeeds.
+    let size = unsafe {
+        let mut size: libc::winsize = mem::zeroed();
+
+        (libc::ioctl(io::stdout().as_raw_fd(), libc::TIOCGWINSZ, &mut size) == 0)
+            .then_some(size.ws_col as usize)
+    };
+
+    size.filter(|width| *width > 0)
+        .or_else(|| env::var("COLUMNS").ok()?.parse().ok())
+        .unwrap_or(80)
+}
+
+/// Format `duration` as `4.2s` or `3m07s`.
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+
+    if seconds < 60 {
+        format!("{:.1}s", duration.as_secs_f32())
+    } else {
+        format!("{}m{:02}s", seconds / 60, seconds % 60)
+    }
+}
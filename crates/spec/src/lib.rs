@@ -0,0 +1,71 @@
+use {
+    serde::{Deserialize, Serialize},
+    serde_yaml::Mapping,
+    std::{collections::BTreeSet, fmt},
+};
+
+/// A package built from one or more parts.
+///
+/// Artifacts are kept generic, so whoever installs them decides their syntax.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Serialized<A> {
+    /// The first one is the primary source.
+    pub sources: Vec<String>,
+    /// Tag, branch or commit of the primary source to build instead of its
+    /// default branch.
+    #[serde(default, rename = "ref", skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+    pub parts: Vec<Part<A>>,
+}
+
+/// Something built with one backend.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Part<A> {
+    /// Defaults to the backend, see [`PartKind::as_str`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub uses: PartKind,
+    /// One of `sources`, may be omitted if there is only one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Parts of the same spec to build first, or packages to install first.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub depends: BTreeSet<String>,
+    #[serde(default = "Vec::new", skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<A>,
+    /// Options of the backend.
+    #[serde(default, skip_serializing_if = "Mapping::is_empty")]
+    pub with: Mapping,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PartKind {
+    Rust,
+    CCpp,
+    Copy,
+    Zig,
+}
+
+impl<A> Part<A> {
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(self.uses.as_str())
+    }
+}
+
+impl PartKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Rust => "rust",
+            Self::CCpp => "c_cpp",
+            Self::Copy => "copy",
+            Self::Zig => "zig",
+        }
+    }
+}
+
+impl fmt::Display for PartKind {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str(self.as_str())
+    }
+}
@@ -1,5 +1,10 @@
-use std::{fmt, str};
+use {
+    super::root::Root,
+    camino::{Utf8Path, Utf8PathBuf},
+    std::{fmt, str},
+};
 
+#[derive(Clone)]
 pub enum Artifact {
     Bin {
         name: Box<str>,
@@ -9,6 +14,181 @@ pub enum Artifact {
         name: Box<str>,
         points_to: Box<str>,
     },
+    /// A library built by zig or cargo, `lib static <name>` or `lib shared <name>`.
+    Lib {
+        kind: LibKind,
+        name: Box<str>,
+        rename_to: Option<Box<str>>,
+    },
+    /// A header of the source.
+    Header {
+        path: Box<str>,
+        rename_to: Option<Box<str>>,
+    },
+    /// A manual page of the source, the extension is the section.
+    Man {
+        path: Box<str>,
+        rename_to: Option<Box<str>>,
+    },
+    /// Any other file of the source.
+    Data {
+        path: Box<str>,
+        rename_to: Option<Box<str>>,
+    },
+    /// A shell completion of the source, `completion <shell> <path>`.
+    Completion {
+        shell: Shell,
+        path: Box<str>,
+        rename_to: Option<Box<str>>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LibKind {
+    Static,
+    Shared,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Shell {
+    Bash,
+    Fish,
+    Zsh,
+}
+
+impl Artifact {
+    /// The kind of artifact, as written in specs.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Bin { .. } => "bin",
+            Self::Sym { .. } => "sym",
+            Self::Lib { .. } => "lib",
+            Self::Header { .. } => "header",
+            Self::Man { .. } => "man",
+            Self::Data { .. } => "data",
+            Self::Completion { .. } => "completion",
+        }
+    }
+
+    /// The name of the artifact as installed.
+    pub fn file_name(&self) -> String {
+        match self {
+            Self::Bin { name, rename_to } => rename_to.as_deref().unwrap_or(name).into(),
+            Self::Sym { name, .. } => name.to_string(),
+            Self::Lib {
+                rename_to: Some(rename_to),
+                ..
+            } => rename_to.to_string(),
+            Self::Lib { kind, name, .. } => kind.file_name(name),
+            Self::Header { path, rename_to }
+            | Self::Man { path, rename_to }
+            | Self::Data { path, rename_to }
+            | Self::Completion {
+                path, rename_to, ..
+            } => rename_to
+                .as_deref()
+                .or_else(|| Utf8Path::new(path).file_name())
+                .unwrap_or(path)
+                .into(),
+        }
+    }
+
+    /// Where the artifact of `package` is installed in `root`.
+    ///
+    /// ```text
+    /// bin         <root>/bin
+    /// sym         <root>/bin
+    /// lib         <root>/lib
+    /// header      <root>/include
+    /// man         <root>/share/man/man<section>
+    /// data        <root>/share/<package>
+    /// completion  <root>/share/bash-completion/completions
+    ///             <root>/share/fish/vendor_completions.d
+    ///             <root>/share/zsh/site-functions
+    /// ```
+    pub fn destination(&self, root: &Root, package: &str) -> Utf8PathBuf {
+        let file_name = self.file_name();
+
+        let dir = match self {
+            Self::Bin { .. } | Self::Sym { .. } => root.bin_dir(),
+            Self::Lib { .. } => root.lib_dir(),
+            Self::Header { .. } => root.include_dir(),
+            Self::Man { .. } => {
+                // `ssl.1ssl` belongs in `man1`.
+                let section = man_section(&file_name).map_or("1", |section| &section[..1]);
+
+                root.share_dir().join("man").join(format!("man{section}"))
+            }
+            Self::Data { .. } => root.share_dir().join(package),
+            Self::Completion { shell, .. } => root.share_dir().join(shell.completion_dir()),
+        };
+
+        dir.join(file_name)
+    }
+}
+
+impl LibKind {
+    /// The file name of library `name`.
+    pub fn file_name(&self, name: &str) -> String {
+        match self {
+            Self::Static => format!("lib{name}.a"),
+            Self::Shared => format!("lib{name}.so"),
+        }
+    }
+}
+
+impl Shell {
+    fn completion_dir(&self) -> &'static str {
+        match self {
+            Self::Bash => "bash-completion/completions",
+            Self::Fish => "fish/vendor_completions.d",
+            Self::Zsh => "zsh/site-functions",
+        }
+    }
+}
+
+impl str::FromStr for LibKind {
+    type Err = &'static str;
+
+    fn from_str(kind: &str) -> std::result::Result<Self, Self::Err> {
+        match kind {
+            "static" => Ok(Self::Static),
+            "shared" => Ok(Self::Shared),
+            _ => Err("library kind must be `static` or `shared`"),
+        }
+    }
+}
+
+impl str::FromStr for Shell {
+    type Err = &'static str;
+
+    fn from_str(shell: &str) -> std::result::Result<Self, Self::Err> {
+        match shell {
+            "bash" => Ok(Self::Bash),
+            "fish" => Ok(Self::Fish),
+            "zsh" => Ok(Self::Zsh),
+            _ => Err("shell must be `bash`, `fish` or `zsh`"),
+        }
+    }
+}
+
+impl fmt::Display for LibKind {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Static => fmt.write_str("static"),
+            Self::Shared => fmt.write_str("shared"),
+        }
+    }
+}
+
+impl fmt::Display for Shell {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bash => fmt.write_str("bash"),
+            Self::Fish => fmt.write_str("fish"),
+            Self::Zsh => fmt.write_str("zsh"),
+        }
+    }
 }
 
 impl str::FromStr for Artifact {
@@ -24,6 +204,46 @@ impl str::FromStr for Artifact {
                 name: Box::from(name),
                 points_to: Box::from(to),
             }),
+            Some(("lib", name, to)) => {
+                let (kind, name) = name.split_once(' ').ok_or("missing library kind")?;
+
+                Ok(Self::Lib {
+                    kind: kind.parse()?,
+                    name: Box::from(name),
+                    rename_to: to.map(Box::from),
+                })
+            }
+            Some(("header", path, to)) => Ok(Self::Header {
+                path: Box::from(path),
+                rename_to: to.map(Box::from),
+            }),
+            Some(("man", path, to)) => {
+                let file_name = to
+                    .or_else(|| Utf8Path::new(path).file_name())
+                    .unwrap_or(path);
+
+                if man_section(file_name).is_none() {
+                    return Err("manual page must end in its section, such as `.1`");
+                }
+
+                Ok(Self::Man {
+                    path: Box::from(path),
+                    rename_to: to.map(Box::from),
+                })
+            }
+            Some(("data", path, to)) => Ok(Self::Data {
+                path: Box::from(path),
+                rename_to: to.map(Box::from),
+            }),
+            Some(("completion", path, to)) => {
+                let (shell, path) = path.split_once(' ').ok_or("missing completion shell")?;
+
+                Ok(Self::Completion {
+                    shell: shell.parse()?,
+                    path: Box::from(path),
+                    rename_to: to.map(Box::from),
+                })
+            }
             _ => Err("invalid artifact mapping"),
         }
     }
@@ -45,6 +265,24 @@ impl fmt::Debug for Artifact {
             Self::Sym { name, points_to } => {
                 fmt.debug_tuple("Sym").field(name).field(points_to).finish()
             }
+
+            Self::Lib {
+                kind,
+                name,
+                rename_to,
+            } => debug_tuple(fmt, "Lib", &[&kind, &name], rename_to),
+
+            Self::Header { path, rename_to } => debug_tuple(fmt, "Header", &[&path], rename_to),
+
+            Self::Man { path, rename_to } => debug_tuple(fmt, "Man", &[&path], rename_to),
+
+            Self::Data { path, rename_to } => debug_tuple(fmt, "Data", &[&path], rename_to),
+
+            Self::Completion {
+                shell,
+                path,
+                rename_to,
+            } => debug_tuple(fmt, "Completion", &[&shell, &path], rename_to),
         }
     }
 }
@@ -63,6 +301,39 @@ impl fmt::Display for Artifact {
             } => write!(fmt, "bin {name}"),
 
             Self::Sym { name, points_to } => write!(fmt, "sym {name} -> {points_to}"),
+
+            Self::Lib {
+                kind,
+                name,
+                rename_to,
+            } => {
+                write!(fmt, "lib {kind} {name}")?;
+                write_rename(fmt, rename_to)
+            }
+
+            Self::Header { path, rename_to } => {
+                write!(fmt, "header {path}")?;
+                write_rename(fmt, rename_to)
+            }
+
+            Self::Man { path, rename_to } => {
+                write!(fmt, "man {path}")?;
+                write_rename(fmt, rename_to)
+            }
+
+            Self::Data { path, rename_to } => {
+                write!(fmt, "data {path}")?;
+                write_rename(fmt, rename_to)
+            }
+
+            Self::Completion {
+                shell,
+                path,
+                rename_to,
+            } => {
+                write!(fmt, "completion {shell} {path}")?;
+                write_rename(fmt, rename_to)
+            }
         }
     }
 }
@@ -117,3 +388,119 @@ fn split_once<'a>(artifact: &'a str) -> Option<(&'a str, &'a str, Option<&'a str
 
     Some((kind, name, to))
 }
+
+fn debug_tuple(
+    fmt: &mut fmt::Formatter<'_>,
+    name: &str,
+    fields: &[&dyn fmt::Debug],
+    rename_to: &Option<Box<str>>,
+) -> fmt::Result {
+    let mut tuple = fmt.debug_tuple(name);
+
+    for field in fields {
+        tuple.field(field);
+    }
+
+    if let Some(rename_to) = rename_to {
+        tuple.field(rename_to);
+    }
+
+    tuple.finish()
+}
+
+fn write_rename(fmt: &mut fmt::Formatter<'_>, rename_to: &Option<Box<str>>) -> fmt::Result {
+    match rename_to {
+        Some(to) => write!(fmt, " -> {to}"),
+        None => Ok(()),
+    }
+}
+
+/// The section of manual page `file_name`, such as `1` for `milk.1`.
+fn man_section(file_name: &str) -> Option<&str> {
+    let (_, section) = file_name.rsplit_once('.')?;
+
+    section
+        .starts_with(|character: char| character.is_ascii_digit())
+        .then_some(section)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check that `artifact` parses, and prints and serializes as itself.
+    fn round_trip(artifact: &str) -> Artifact {
+        let parsed = artifact.parse::<Artifact>().unwrap();
+
+        assert_eq!(parsed.to_string(), artifact);
+
+        let yaml = serde_yaml::to_string(&parsed).unwrap();
+        let deserialized = serde_yaml::from_str::<Artifact>(&yaml).unwrap();
+
+        assert_eq!(deserialized.to_string(), artifact);
+
+        parsed
+    }
+
+    #[test]
+    fn bin() {
+        round_trip("bin milk");
+        round_trip("bin milk -> mk");
+    }
+
+    #[test]
+    fn sym() {
+        round_trip("sym mk -> milk");
+    }
+
+    #[test]
+    fn lib() {
+        let artifact = round_trip("lib static zstd");
+
+        assert_eq!(artifact.file_name(), "libzstd.a");
+        assert!(matches!(
+            round_trip("lib shared zstd -> libzstd.so.1"),
+            Artifact::Lib {
+                kind: LibKind::Shared,
+                rename_to: Some(_),
+                ..
+            }
+        ));
+
+        assert!("lib zstd".parse::<Artifact>().is_err());
+        assert!("lib dynamic zstd".parse::<Artifact>().is_err());
+    }
+
+    #[test]
+    fn header() {
+        round_trip("header lib/zstd.h");
+        round_trip("header lib/zstd.h -> zstd/zstd.h");
+    }
+
+    #[test]
+    fn man() {
+        round_trip("man doc/zstd.1");
+        round_trip("man doc/ssl.1ssl");
+        round_trip("man doc/zstd.md -> zstd.1");
+
+        assert!("man doc/zstd.md".parse::<Artifact>().is_err());
+        assert!("man doc/zstd".parse::<Artifact>().is_err());
+        assert!("man doc/zstd.1 -> zstd".parse::<Artifact>().is_err());
+    }
+
+    #[test]
+    fn data() {
+        round_trip("data share/dict");
+        round_trip("data share/dict -> dictionary");
+    }
+
+    #[test]
+    fn completion() {
+        round_trip("completion bash contrib/zstd");
+        round_trip("completion fish contrib/zstd.fish");
+        round_trip("completion zsh contrib/_zstd -> _zstd");
+
+        assert!("completion contrib/zstd".parse::<Artifact>().is_err());
+        assert!("completion nu contrib/zstd".parse::<Artifact>().is_err());
+    }
+}
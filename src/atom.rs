@@ -1,28 +1,96 @@
 use {
     milk_target::{Target, TargetError},
-    std::{fmt, str::FromStr},
+    std::{error, fmt, str::FromStr},
 };
 
+/// A package to install, written `<repository>/<package>[<features>]=<ref>@<target>`.
+///
+/// Only the package is required, e.g. `zstd` or `main/zstd[+lz4,-legacy]=v1.5.5@musl`.
 #[derive(Clone, Eq, PartialEq)]
 pub struct Atom {
+    /// Repository the package is pinned to.
+    pub repository: Option<String>,
     pub package: String,
+    /// Changes to the features listed in the spec.
+    pub features: Vec<Feature>,
+    /// Tag, branch or commit to build, overrides the spec.
+    pub reference: Option<String>,
     pub target: Target,
 }
 
+/// A change to the cargo features of a package.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Feature {
+    /// `+<feature>`, or just `<feature>`.
+    Enable(String),
+    /// `-<feature>`.
+    Disable(String),
+}
+
+/// Why an atom could not be parsed.
+#[derive(Debug)]
+pub enum AtomError {
+    Empty,
+    /// `=` without a ref.
+    Reference,
+    /// Unbalanced brackets or an empty feature.
+    Features,
+    /// An empty package or repository.
+    Package,
+    Target(TargetError),
+}
+
 impl FromStr for Atom {
-    type Err = TargetError;
+    type Err = AtomError;
 
     fn from_str(atom: &str) -> Result<Self, Self::Err> {
         if atom.is_empty() {
-            Err(TargetError::Invalid(Box::from("empty atom")))
+            Err(AtomError::Empty)
         } else {
             let (package, target) = match atom.split_once('@') {
-                Some((package, target)) => (package, target.parse()?),
+                Some((package, target)) => (package, target.parse().map_err(AtomError::Target)?),
                 None => (atom, Target::HOST),
             };
 
+            let (package, reference) = match package.split_once('=') {
+                Some((_, "")) => return Err(AtomError::Reference),
+                Some((package, reference)) => (package, Some(reference)),
+                None => (package, None),
+            };
+
+            let (package, features) = match package.strip_suffix(']') {
+                Some(package) => {
+                    let (package, features) = package.split_once('[').ok_or(AtomError::Features)?;
+
+                    let features = features
+                        .split(',')
+                        .map(Feature::from_str)
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or(AtomError::Features)?;
+
+                    (package, features)
+                }
+                None => (package, Vec::new()),
+            };
+
+            let (repository, package) = match package.split_once('/') {
+                Some((repository, package)) => (Some(repository), package),
+                None => (None, package),
+            };
+
+            if package.contains(['[', ']']) {
+                return Err(AtomError::Features);
+            }
+
+            if package.is_empty() || repository.is_some_and(str::is_empty) {
+                return Err(AtomError::Package);
+            }
+
             Ok(Self {
+                repository: repository.map(String::from),
                 package: String::from(package),
+                features,
+                reference: reference.map(String::from),
                 target,
             })
         }
@@ -38,8 +106,213 @@ impl fmt::Debug for Atom {
 
 impl fmt::Display for Atom {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let Self { package, target } = self;
+        let Self {
+            repository,
+            package,
+            features,
+            reference,
+            target,
+        } = self;
+
+        if let Some(repository) = repository {
+            write!(fmt, "{repository}/")?;
+        }
+
+        write!(fmt, "{package}")?;
+
+        if !features.is_empty() {
+            let features = features
+                .iter()
+                .map(Feature::to_string)
+                .collect::<Vec<_>>()
+                .join(",");
+
+            write!(fmt, "[{features}]")?;
+        }
+
+        if let Some(reference) = reference {
+            write!(fmt, "={reference}")?;
+        }
+
+        write!(fmt, "@{target}")
+    }
+}
+
+impl fmt::Display for AtomError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => fmt.write_str("empty atom"),
+            Self::Reference => fmt.write_str("`=` must be followed by a tag, branch or commit"),
+            Self::Features => fmt.write_str("features must be written `[+<feature>,-<feature>]`"),
+            Self::Package => fmt.write_str("expected `<package>` or `<repository>/<package>`"),
+            Self::Target(error) => fmt::Display::fmt(error, fmt),
+        }
+    }
+}
+
+impl error::Error for AtomError {}
+
+impl Feature {
+    /// Parse `+<feature>`, `-<feature>` or `<feature>`.
+    fn from_str(feature: &str) -> Option<Self> {
+        let feature = match feature.strip_prefix('-') {
+            Some(feature) => Self::Disable(feature.into()),
+            None => Self::Enable(feature.strip_prefix('+').unwrap_or(feature).into()),
+        };
+
+        match &feature {
+            Self::Enable(name) | Self::Disable(name) if name.is_empty() => None,
+            _ => Some(feature),
+        }
+    }
+
+    /// `features` with `changes` applied.
+    pub fn apply(changes: &[Feature], features: &[String]) -> Vec<String> {
+        let mut features = features.to_vec();
+
+        for change in changes {
+            match change {
+                Feature::Enable(feature) if !features.contains(feature) => {
+                    features.push(feature.clone())
+                }
+                Feature::Disable(feature) => features.retain(|enabled| enabled != feature),
+                _ => {}
+            }
+        }
+
+        features
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Enable(feature) => write!(fmt, "+{feature}"),
+            Self::Disable(feature) => write!(fmt, "-{feature}"),
+        }
+    }
+}
+
+impl serde::Serialize for Atom {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Atom {
+    fn deserialize<D>(deserializer: D) -> Result<Atom, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de::{Error, Unexpected, Visitor};
+
+        struct AtomVisitor;
+
+        impl<'de> Visitor<'de> for AtomVisitor {
+            type Value = Atom;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a string representing an atom")
+            }
+
+            fn visit_str<E>(self, atom: &str) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                atom.parse::<Atom>().map_err(|error| {
+                    let error = error.to_string();
+
+                    Error::invalid_value(Unexpected::Str(atom), &error.as_str())
+                })
+            }
+        }
+
+        deserializer.deserialize_str(AtomVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check that `atom` parses, and prints and serializes as itself.
+    fn round_trip(atom: &str) -> Atom {
+        let parsed = atom.parse::<Atom>().unwrap();
+
+        assert_eq!(parsed.to_string(), atom);
+
+        let yaml = serde_yaml::to_string(&parsed).unwrap();
+        let deserialized = serde_yaml::from_str::<Atom>(&yaml).unwrap();
+
+        assert_eq!(deserialized.to_string(), atom);
+
+        parsed
+    }
+
+    #[test]
+    fn package() {
+        let atom = "zstd".parse::<Atom>().unwrap();
+
+        assert_eq!(atom.package, "zstd");
+        assert_eq!(atom.repository, None);
+        assert_eq!(atom.target, Target::HOST);
+
+        round_trip("zstd@x86_64-musl-static");
+    }
+
+    #[test]
+    fn full() {
+        let atom = round_trip("main/zstd[+lz4,-legacy]=v1.5.5@arm64-gnu-dynamic");
+
+        assert_eq!(atom.repository.as_deref(), Some("main"));
+        assert_eq!(atom.package, "zstd");
+        assert_eq!(
+            atom.features,
+            [
+                Feature::Enable("lz4".into()),
+                Feature::Disable("legacy".into())
+            ]
+        );
+        assert_eq!(atom.reference.as_deref(), Some("v1.5.5"));
+    }
+
+    #[test]
+    fn features() {
+        let atom = "zstd[lz4]".parse::<Atom>().unwrap();
+
+        assert_eq!(atom.features, [Feature::Enable("lz4".into())]);
+    }
+
+    /// `@` is split off first, then `=`, then `[]`, then `/`.
+    #[test]
+    fn order() {
+        let atom = round_trip("zstd=release/1.5@x86_64-musl-static");
+
+        assert_eq!(atom.repository, None);
+        assert_eq!(atom.reference.as_deref(), Some("release/1.5"));
+
+        let atom = "zstd=v1[+lz4]".parse::<Atom>().unwrap();
+
+        assert!(atom.features.is_empty());
+        assert_eq!(atom.reference.as_deref(), Some("v1[+lz4]"));
+    }
+
+    #[test]
+    fn invalid() {
+        let error = |atom: &str| atom.parse::<Atom>().unwrap_err();
 
-        write!(fmt, "{package}@{target}")
+        assert!(matches!(error(""), AtomError::Empty));
+        assert!(matches!(error("zstd="), AtomError::Reference));
+        assert!(matches!(error("zstd["), AtomError::Features));
+        assert!(matches!(error("zstd]"), AtomError::Features));
+        assert!(matches!(error("zstd[]"), AtomError::Features));
+        assert!(matches!(error("zstd[+lz4,]"), AtomError::Features));
+        assert!(matches!(error("[lz4]"), AtomError::Package));
+        assert!(matches!(error("/zstd"), AtomError::Package));
+        assert!(matches!(error("main/"), AtomError::Package));
+        assert!(matches!(error("zstd@mips"), AtomError::Target(_)));
     }
 }
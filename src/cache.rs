@@ -0,0 +1,221 @@
+use {
+    super::{git::git, log::Log, root::Root, source::remove_dir_all},
+    camino::{Utf8Path, Utf8PathBuf},
+    sha2::{Digest, Sha256},
+    std::{
+        fs::{self, File, OpenOptions},
+        io,
+        os::fd::AsRawFd,
+        process::{Command, Stdio},
+    },
+};
+
+/// Fetched sources, so packages can be built without network access.
+///
+/// ```text
+/// <root>/cache/git/<sha256>-<name>.git   bare mirrors, named after the checksum of their url
+/// <root>/cache/tarball/<sha256>          verified tarballs, named after their checksum
+/// ```
+///
+/// Entries are locked while in use, as concurrent installs may share a
+/// source.
+pub struct Cache {
+    dir: Utf8PathBuf,
+    /// Only use what is cached.
+    offline: bool,
+}
+
+/// A cache entry, locked until dropped.
+pub struct Entry {
+    path: Utf8PathBuf,
+    _lock: File,
+}
+
+impl Entry {
+    pub fn path(&self) -> &Utf8Path {
+        &self.path
+    }
+}
+
+impl Cache {
+    pub fn new(root: &Root, offline: bool) -> Self {
+        Self {
+            dir: root.cache_dir(),
+            offline,
+        }
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    /// Update the mirror of the git repository at `url` and return it.
+    ///
+    /// Offline, the mirror is used as it is.
+    pub fn git(&self, url: &str, log: &mut Log) -> io::Result<Entry> {
+        let dir = self.dir.join("git");
+        let mirror = dir.join(mirror_name(url));
+
+        fs::create_dir_all(&dir)?;
+
+        let lock = lock(&mirror)?;
+
+        if mirror.exists() {
+            if !self.offline {
+                log.run(git(&mirror).args(["fetch", "--quiet", "--prune", "origin"]))?;
+            }
+
+            return Ok(Entry {
+                path: mirror,
+                _lock: lock,
+            });
+        }
+
+        if self.offline {
+            return Err(not_cached(url));
+        }
+
+        // Cloned aside, an interrupted clone is not mistaken for a mirror.
+        let partial = mirror.with_extension("partial");
+
+        remove_dir_all(&partial)?;
+
+        log.run(
+            git(&dir)
+                .args(["clone", "--quiet", "--mirror", url])
+                .arg(&partial),
+        )?;
+
+        fs::rename(&partial, &mirror)?;
+
+        Ok(Entry {
+            path: mirror,
+            _lock: lock,
+        })
+    }
+
+    /// Download the tarball at `url` unless cached, and return it.
+    ///
+    /// The tarball is verified against `sha256` either way.
+    pub fn tarball(&self, url: &str, sha256: &str, log: &mut Log) -> io::Result<Entry> {
+        let dir = self.dir.join("tarball");
+        let tarball = dir.join(sha256);
+
+        fs::create_dir_all(&dir)?;
+
+        let lock = lock(&tarball)?;
+
+        if tarball.exists() {
+            verify(&tarball, sha256, log)?;
+
+            return Ok(Entry {
+                path: tarball,
+                _lock: lock,
+            });
+        }
+
+        if self.offline {
+            return Err(not_cached(url));
+        }
+
+        let partial = tarball.with_extension("partial");
+
+        log.run(
+            Command::new("curl")
+                .args([
+                    "--fail",
+                    "--location",
+                    "--silent",
+                    "--show-error",
+                    "--output",
+                ])
+                .arg(&partial)
+                .arg(url)
+                .stdin(Stdio::null()),
+        )?;
+
+        if let Err(error) = verify(&partial, sha256, log) {
+            let _ = fs::remove_file(&partial);
+
+            return Err(error);
+        }
+
+        fs::rename(&partial, &tarball)?;
+
+        Ok(Entry {
+            path: tarball,
+            _lock: lock,
+        })
+    }
+}
+
+/// A file name for the mirror of `url`.
+///
+/// The SHA-256 of the url without a trailing `/` or `.git`, followed by
+/// its last segment to be recognizable, so `https://github.com/facebook/zstd`
+/// is `<sha256>-zstd.git`.
+fn mirror_name(url: &str) -> String {
+    let url = url.trim_end_matches('/');
+    let url = url.strip_suffix(".git").unwrap_or(url);
+
+    let name = url
+        .rsplit(['/', ':'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|character| {
+            character.is_ascii_alphanumeric() || matches!(character, '-' | '_' | '.')
+        })
+        .collect::<String>();
+
+    format!("{}-{name}.git", hex(&Sha256::digest(url)))
+}
+
+/// Hold an exclusive lock on the cache entry at `path` until it is dropped.
+///
+/// The lock is an `flock` on `<path>.lock`, so it is released when milk exits.
+fn lock(path: &Utf8Path) -> io::Result<File> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(format!("{path}.lock"))?;
+
+    // SAFETY: The descriptor is owned by `file`, which outlives the call.
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(file)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn not_cached(url: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("`{url}` is not cached, run `milk fetch` while online"),
+    )
+}
+
+/// Check the SHA-256 of `path`.
+fn verify(path: &Utf8Path, sha256: &str, log: &mut Log) -> io::Result<()> {
+    log.line(format_args!("verifying sha256 of {path}"));
+
+    let mut hasher = Sha256::new();
+
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+
+    let actual = hex(&hasher.finalize());
+
+    if actual.eq_ignore_ascii_case(sha256) {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("checksum mismatch, expected sha256 {sha256}, found {actual}"),
+        ))
+    }
+}
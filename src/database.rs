@@ -0,0 +1,189 @@
+use {
+    super::{package, root::Root, Artifact, Atom, Error, Result},
+    camino::Utf8PathBuf,
+    serde::{Deserialize, Serialize},
+    std::{fs, io},
+};
+
+/// Installed-state database.
+///
+/// Stored as YAML at `<root>/installed.yaml`.
+#[derive(Debug)]
+pub struct Database {
+    path: Utf8PathBuf,
+    serialized: Serialized,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Serialized {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    installed: Vec<Record>,
+}
+
+/// An installed package.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Record {
+    /// The package and target that were installed.
+    pub atom: Atom,
+    /// Repository the spec was found in.
+    pub repository: String,
+    /// Path to the spec the package was installed from.
+    pub spec: Utf8PathBuf,
+    /// Source the package was fetched from.
+    pub source: String,
+    /// Tag, branch or commit that was requested.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+    /// Commit of the source that was built.
+    pub revision: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<InstalledArtifact>,
+}
+
+/// Files produced by an artifact.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct InstalledArtifact {
+    pub artifact: Artifact,
+    pub files: Vec<Utf8PathBuf>,
+}
+
+impl Database {
+    /// Open the database in `root`.
+    ///
+    /// A missing database is treated as empty.
+    pub fn open(root: &Root) -> Result<Self> {
+        let path = root.path().join("installed.yaml");
+
+        let serialized = match fs::read_to_string(&path) {
+            Ok(content) => serde_yaml::from_str(&content)
+                .map_err(|error| Error::deserialize("package database", &path, &content, error))?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Serialized::default(),
+            Err(error) => return Err(Error::io(&path, error)),
+        };
+
+        Ok(Self { path, serialized })
+    }
+
+    /// Write the database back to disk.
+    ///
+    /// The previous state is replaced atomically.
+    pub fn save(&self) -> Result<()> {
+        let content = serde_yaml::to_string(&self.serialized).unwrap();
+        let temporary_path = self.path.with_extension("yaml.new");
+
+        fs::write(&temporary_path, content).map_err(|error| Error::io(&temporary_path, error))?;
+        fs::rename(&temporary_path, &self.path).map_err(|error| Error::io(&self.path, error))?;
+
+        Ok(())
+    }
+
+    /// Iterate all installed packages.
+    pub fn iter(&self) -> impl Iterator<Item = &Record> {
+        self.serialized.installed.iter()
+    }
+
+    /// Find the record of an installed atom.
+    pub fn get(&self, atom: &Atom) -> Option<&Record> {
+        self.iter().find(|record| record.matches(atom))
+    }
+
+    /// Find all installed packages that depend on `atom`.
+    pub fn dependents<'a>(&'a self, atom: &'a Atom) -> impl Iterator<Item = &'a Record> {
+        self.iter().filter(move |record| {
+            record.atom.target == atom.target
+                && record.dependencies.iter().any(|dependency| {
+                    // Dependencies may be pinned with `<repository>/<package>`.
+                    let name = dependency
+                        .split_once('/')
+                        .map_or(dependency.as_str(), |(_, name)| name);
+
+                    name == atom.package
+                })
+        })
+    }
+
+    /// Files installed for `atom` that no other record installed too.
+    pub fn owned_files(&self, atom: &Atom) -> Vec<Utf8PathBuf> {
+        let Some(record) = self.get(atom) else {
+            return Vec::new();
+        };
+
+        record
+            .files()
+            .filter(|file| {
+                self.iter()
+                    .filter(|other| !other.matches(atom))
+                    .all(|other| other.files().all(|other_file| other_file != *file))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Find all installed targets of a package.
+    pub fn get_package<'a>(&'a self, package: &'a str) -> impl Iterator<Item = &'a Record> {
+        self.iter()
+            .filter(move |record| record.atom.package == package)
+    }
+
+    /// Insert a record, replacing any previous record of the same atom.
+    pub fn insert(&mut self, record: Record) {
+        self.remove(&record.atom);
+        self.serialized.installed.push(record);
+        self.serialized
+            .installed
+            .sort_by_key(|record| record.atom.to_string());
+    }
+
+    /// Remove the record of an atom, returning it.
+    pub fn remove(&mut self, atom: &Atom) -> Option<Record> {
+        let index = self
+            .serialized
+            .installed
+            .iter()
+            .position(|record| record.matches(atom))?;
+
+        Some(self.serialized.installed.remove(index))
+    }
+}
+
+impl Record {
+    /// Whether this record was installed for `atom`.
+    ///
+    /// Atoms without a repository match records from any repository.
+    pub fn matches(&self, atom: &Atom) -> bool {
+        self.atom.package == atom.package
+            && self.atom.target == atom.target
+            && atom
+                .repository
+                .as_ref()
+                .is_none_or(|repository| *repository == self.repository)
+    }
+
+    /// Iterate the files produced by the artifacts of this record.
+    pub fn files(&self) -> impl Iterator<Item = &Utf8PathBuf> {
+        self.artifacts
+            .iter()
+            .flat_map(|installed| installed.files.iter())
+    }
+
+    /// Delete the files produced by the artifacts of this record.
+    ///
+    /// Files that no longer exist are skipped.
+    pub fn uninstall(&self) -> Result<()> {
+        for InstalledArtifact { files, .. } in &self.artifacts {
+            for file in files {
+                match fs::remove_file(file) {
+                    Ok(()) => package::artifact_log("rm", file.as_str(), None),
+                    Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+                    Err(error) => return Err(Error::io(file, error)),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use {
+    super::{Artifact, Atom},
     camino::Utf8Path,
     codespan_reporting::{
         diagnostic::{Diagnostic, Label},
@@ -8,22 +9,101 @@ use {
             termcolor::{ColorChoice, StandardStream},
         },
     },
-    std::{error, fmt, ops::Range, process},
+    std::{
+        error, fmt, io,
+        ops::Range,
+        process::{self, ExitStatus},
+    },
 };
 
 pub enum Error {
-    DeserializeSpec {
+    Deserialize {
+        /// What the file holds, e.g. `spec`.
+        kind: &'static str,
         source: Box<Utf8Path>,
         content: Box<str>,
         message: Box<str>,
         range: Range<usize>,
     },
+    InvalidSpec {
+        package: Box<str>,
+        message: Box<str>,
+    },
+    UnknownPackage {
+        name: Box<str>,
+        required_by: Option<Box<str>>,
+    },
+    DependencyCycle {
+        cycle: Vec<Box<str>>,
+    },
+    ConflictingPackages {
+        name: Box<str>,
+        repositories: [Box<str>; 2],
+    },
+    ConflictingAtoms {
+        atoms: [Box<str>; 2],
+    },
+    NoLog {
+        package: Box<str>,
+    },
+    NotInstalled {
+        atom: Box<str>,
+    },
+    RequiredBy {
+        atom: Box<str>,
+        dependents: Vec<Box<str>>,
+    },
+    Toolchain {
+        tool: Box<str>,
+        message: Box<str>,
+    },
+    Jobserver {
+        message: Box<str>,
+    },
+    Fetch {
+        package: Box<str>,
+        source: Box<str>,
+        message: Box<str>,
+    },
+    Build {
+        package: Box<str>,
+        step: Box<str>,
+        status: Option<ExitStatus>,
+        message: Option<Box<str>>,
+        log_tail: Vec<Box<str>>,
+    },
+    MissingArtifact {
+        package: Box<str>,
+        artifact: Box<str>,
+        /// Artifacts of the same kind that were built.
+        built: Vec<Box<str>>,
+    },
+    Permission {
+        path: Box<Utf8Path>,
+        message: Box<str>,
+    },
+    Io {
+        path: Box<Utf8Path>,
+        message: Box<str>,
+    },
 }
 
+/// Lines of build output kept for diagnostics.
+const LOG_TAIL_LINES: usize = 20;
+
 impl Error {
+    /// Report the error and exit.
     pub fn emit(self) -> ! {
+        self.report();
+
+        process::exit(1)
+    }
+
+    /// Report the error without exiting.
+    pub fn report(self) {
         match self {
-            Error::DeserializeSpec {
+            Error::Deserialize {
+                kind,
                 source,
                 content,
                 message,
@@ -38,7 +118,7 @@ impl Error {
                             .with_message("unexpected character encountered")
                             .with_notes(vec![String::from("perhaps surround it in quotes?")])
                     } else {
-                        diagnostic.with_message("failed to parse spec as yaml")
+                        diagnostic.with_message(format!("failed to parse {kind} as yaml"))
                     };
 
                 let source = SimpleFile::new(source.as_str(), &content);
@@ -47,12 +127,180 @@ impl Error {
                 term::emit(&mut output, &Default::default(), &source, &diagnostic)
                     .unwrap_or_else(|_| panic!("{message}"));
             }
-        }
+            Error::InvalidSpec { package, message } => {
+                let diagnostic = Diagnostic::error()
+                    .with_message(format!("invalid spec for `{package}`"))
+                    .with_notes(vec![message.into_string()]);
 
-        process::exit(1)
+                emit_diagnostic(&diagnostic);
+            }
+            Error::UnknownPackage { name, required_by } => {
+                let diagnostic =
+                    Diagnostic::error().with_message(format!("unknown package `{name}`"));
+                let diagnostic = match required_by {
+                    Some(required_by) => diagnostic
+                        .with_notes(vec![format!("required as a dependency of `{required_by}`")]),
+                    None => {
+                        diagnostic.with_notes(vec![String::from("perhaps sync your repositories?")])
+                    }
+                };
+
+                emit_diagnostic(&diagnostic);
+            }
+            Error::DependencyCycle { cycle } => {
+                let diagnostic = Diagnostic::error()
+                    .with_message("dependency cycle detected")
+                    .with_notes(vec![cycle.join(" -> ")]);
+
+                emit_diagnostic(&diagnostic);
+            }
+            Error::ConflictingPackages { name, repositories } => {
+                let [first, second] = repositories;
+                let diagnostic = Diagnostic::error()
+                    .with_message(format!(
+                        "`{name}` is requested from both `{first}` and `{second}`"
+                    ))
+                    .with_notes(vec![String::from(
+                        "packages of the same name cannot be installed side by side",
+                    )]);
+
+                emit_diagnostic(&diagnostic);
+            }
+            Error::ConflictingAtoms { atoms } => {
+                let [first, second] = atoms;
+                let diagnostic = Diagnostic::error()
+                    .with_message(format!("both `{first}` and `{second}` are requested"))
+                    .with_notes(vec![String::from(
+                        "a package is built once per target, with one set of features and ref",
+                    )]);
+
+                emit_diagnostic(&diagnostic);
+            }
+            Error::NoLog { package } => {
+                let diagnostic = Diagnostic::error()
+                    .with_message(format!("no build log for `{package}`"))
+                    .with_notes(vec![String::from("logs are written by `milk add`")]);
+
+                emit_diagnostic(&diagnostic);
+            }
+            Error::NotInstalled { atom } => {
+                let diagnostic =
+                    Diagnostic::error().with_message(format!("`{atom}` is not installed"));
+
+                emit_diagnostic(&diagnostic);
+            }
+            Error::RequiredBy { atom, dependents } => {
+                let diagnostic = Diagnostic::error()
+                    .with_message(format!("`{atom}` is required by other packages"))
+                    .with_notes(vec![
+                        format!("required by {}", dependents.join(", ")),
+                        String::from("use `--force` to remove it anyway"),
+                    ]);
+
+                emit_diagnostic(&diagnostic);
+            }
+            Error::Toolchain { tool, message } => {
+                let diagnostic = Diagnostic::error()
+                    .with_message(format!("unusable {tool} toolchain"))
+                    .with_notes(vec![
+                        message.into_string(),
+                        format!("use `--{tool}-path` to select a different binary"),
+                    ]);
+
+                emit_diagnostic(&diagnostic);
+            }
+            Error::Jobserver { message } => {
+                let diagnostic = Diagnostic::error()
+                    .with_message("failed to create the jobserver")
+                    .with_notes(vec![message.into_string()]);
+
+                emit_diagnostic(&diagnostic);
+            }
+            Error::Fetch {
+                package,
+                source,
+                message,
+            } => {
+                let diagnostic = Diagnostic::error()
+                    .with_message(format!("failed to fetch `{package}`"))
+                    .with_notes(vec![
+                        format!("from {source}"),
+                        message.into_string(),
+                        String::from("check the source of the spec and your network connection"),
+                    ]);
+
+                emit_diagnostic(&diagnostic);
+            }
+            Error::Build {
+                package,
+                step,
+                status,
+                message,
+                log_tail,
+            } => {
+                let mut notes = Vec::new();
+
+                match status {
+                    Some(status) => notes.push(format!("`{step}` failed with {status}")),
+                    None => notes.push(format!("failed to run `{step}`")),
+                }
+
+                notes.extend(message.map(str::into_string));
+
+                if !log_tail.is_empty() {
+                    notes.push(format!("last lines of output:\n{}", log_tail.join("\n")));
+                }
+
+                notes.push(format!("run `milk log {package}` for the full log"));
+
+                let diagnostic = Diagnostic::error()
+                    .with_message(format!("failed to build `{package}`"))
+                    .with_notes(notes);
+
+                emit_diagnostic(&diagnostic);
+            }
+            Error::MissingArtifact {
+                package,
+                artifact,
+                built,
+            } => {
+                let built = if built.is_empty() {
+                    String::from("nothing of that kind was built")
+                } else {
+                    format!("built {}", built.join(", "))
+                };
+
+                let diagnostic = Diagnostic::error()
+                    .with_message(format!("`{package}` did not produce `{artifact}`"))
+                    .with_notes(vec![
+                        built,
+                        String::from("check the artifacts listed in the spec"),
+                    ]);
+
+                emit_diagnostic(&diagnostic);
+            }
+            Error::Permission { path, message } => {
+                let diagnostic = Diagnostic::error()
+                    .with_message(format!("permission denied: {path}"))
+                    .with_notes(vec![
+                        message.into_string(),
+                        String::from("use `--root` to install into a directory you own"),
+                    ]);
+
+                emit_diagnostic(&diagnostic);
+            }
+            Error::Io { path, message } => {
+                let diagnostic = Diagnostic::error()
+                    .with_message(format!("i/o error: {path}"))
+                    .with_notes(vec![message.into_string()]);
+
+                emit_diagnostic(&diagnostic);
+            }
+        }
     }
 
-    pub(crate) fn deserialize_spec(
+    pub(crate) fn deserialize(
+        kind: &'static str,
         path: &Utf8Path,
         content: &str,
         error: serde_yaml::Error,
@@ -70,13 +318,151 @@ impl Error {
 
         let message = error.to_string().into_boxed_str();
 
-        Self::DeserializeSpec {
+        Self::Deserialize {
+            kind,
             source: Box::from(path),
             content: Box::from(content),
             message,
             range,
         }
     }
+
+    pub(crate) fn invalid_spec(package: &str, message: &str) -> Self {
+        Self::InvalidSpec {
+            package: Box::from(package),
+            message: Box::from(message),
+        }
+    }
+
+    pub(crate) fn unknown_package(name: &str, required_by: Option<&str>) -> Self {
+        Self::UnknownPackage {
+            name: Box::from(name),
+            required_by: required_by.map(Box::from),
+        }
+    }
+
+    pub(crate) fn dependency_cycle(cycle: Vec<Box<str>>) -> Self {
+        Self::DependencyCycle { cycle }
+    }
+
+    pub(crate) fn conflicting_packages(name: &str, first: &str, second: &str) -> Self {
+        Self::ConflictingPackages {
+            name: Box::from(name),
+            repositories: [Box::from(first), Box::from(second)],
+        }
+    }
+
+    pub(crate) fn conflicting_atoms(first: &Atom, second: &Atom) -> Self {
+        Self::ConflictingAtoms {
+            atoms: [
+                first.to_string().into_boxed_str(),
+                second.to_string().into_boxed_str(),
+            ],
+        }
+    }
+
+    pub(crate) fn no_log(package: &str) -> Self {
+        Self::NoLog {
+            package: Box::from(package),
+        }
+    }
+
+    pub(crate) fn not_installed(atom: &Atom) -> Self {
+        Self::NotInstalled {
+            atom: atom.to_string().into_boxed_str(),
+        }
+    }
+
+    pub(crate) fn required_by(atom: &Atom, dependents: Vec<Box<str>>) -> Self {
+        Self::RequiredBy {
+            atom: atom.to_string().into_boxed_str(),
+            dependents,
+        }
+    }
+
+    pub(crate) fn toolchain(tool: &str, message: &str) -> Self {
+        Self::Toolchain {
+            tool: Box::from(tool),
+            message: Box::from(message),
+        }
+    }
+
+    pub(crate) fn jobserver(error: io::Error) -> Self {
+        Self::Jobserver {
+            message: error.to_string().into_boxed_str(),
+        }
+    }
+
+    pub(crate) fn fetch(package: &str, source: &str, error: io::Error) -> Self {
+        Self::Fetch {
+            package: Box::from(package),
+            source: Box::from(source),
+            message: error.to_string().into_boxed_str(),
+        }
+    }
+
+    /// A build step that could not be run or did not succeed.
+    ///
+    /// `log` is the captured output of the step, only its tail is kept.
+    pub(crate) fn build(
+        package: &str,
+        step: &str,
+        status: Option<ExitStatus>,
+        message: Option<&str>,
+        log: &str,
+    ) -> Self {
+        let lines = log.lines().collect::<Vec<_>>();
+        let log_tail = lines[lines.len().saturating_sub(LOG_TAIL_LINES)..]
+            .iter()
+            .map(|line| Box::from(*line))
+            .collect();
+
+        Self::Build {
+            package: Box::from(package),
+            step: Box::from(step),
+            status,
+            message: message.map(Box::from),
+            log_tail,
+        }
+    }
+
+    pub(crate) fn missing_artifact<'a, I>(package: &str, artifact: &Artifact, built: I) -> Self
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        Self::MissingArtifact {
+            package: Box::from(package),
+            artifact: artifact.to_string().into_boxed_str(),
+            built: built.into_iter().map(Box::from).collect(),
+        }
+    }
+
+    /// An I/O error involving `path`.
+    ///
+    /// Permission problems are reported separately, as they usually mean
+    /// the Mocha root is not writable.
+    pub(crate) fn io(path: &Utf8Path, error: io::Error) -> Self {
+        if error.kind() == io::ErrorKind::PermissionDenied {
+            Self::Permission {
+                path: Box::from(path),
+                message: error.to_string().into_boxed_str(),
+            }
+        } else {
+            Self::Io {
+                path: Box::from(path),
+                message: error.to_string().into_boxed_str(),
+            }
+        }
+    }
+}
+
+/// Emit a diagnostic that does not refer to any source file.
+fn emit_diagnostic(diagnostic: &Diagnostic<()>) {
+    let source = SimpleFile::new("", "");
+    let mut output = StandardStream::stderr(ColorChoice::Auto);
+
+    term::emit(&mut output, &Default::default(), &source, diagnostic)
+        .unwrap_or_else(|_| panic!("{}", diagnostic.message));
 }
 
 impl error::Error for Error {}
@@ -84,10 +470,92 @@ impl error::Error for Error {}
 impl fmt::Debug for Error {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::DeserializeSpec { source, .. } => fmt
-                .debug_struct("DeserializeSpec")
+            Self::Deserialize { kind, source, .. } => fmt
+                .debug_struct("Deserialize")
+                .field("kind", &kind)
+                .field("source", &source)
+                .finish_non_exhaustive(),
+            Self::InvalidSpec { package, message } => fmt
+                .debug_struct("InvalidSpec")
+                .field("package", &package)
+                .field("message", &message)
+                .finish(),
+            Self::UnknownPackage { name, required_by } => fmt
+                .debug_struct("UnknownPackage")
+                .field("name", &name)
+                .field("required_by", &required_by)
+                .finish(),
+            Self::DependencyCycle { cycle } => fmt
+                .debug_struct("DependencyCycle")
+                .field("cycle", &cycle)
+                .finish(),
+            Self::ConflictingPackages { name, repositories } => fmt
+                .debug_struct("ConflictingPackages")
+                .field("name", &name)
+                .field("repositories", &repositories)
+                .finish(),
+            Self::ConflictingAtoms { atoms } => fmt
+                .debug_struct("ConflictingAtoms")
+                .field("atoms", &atoms)
+                .finish(),
+            Self::NoLog { package } => fmt
+                .debug_struct("NoLog")
+                .field("package", &package)
+                .finish(),
+            Self::NotInstalled { atom } => fmt
+                .debug_struct("NotInstalled")
+                .field("atom", &atom)
+                .finish(),
+            Self::RequiredBy { atom, dependents } => fmt
+                .debug_struct("RequiredBy")
+                .field("atom", &atom)
+                .field("dependents", &dependents)
+                .finish(),
+            Self::Toolchain { tool, message } => fmt
+                .debug_struct("Toolchain")
+                .field("tool", &tool)
+                .field("message", &message)
+                .finish(),
+            Self::Jobserver { message } => fmt
+                .debug_struct("Jobserver")
+                .field("message", &message)
+                .finish(),
+            Self::Fetch {
+                package, source, ..
+            } => fmt
+                .debug_struct("Fetch")
+                .field("package", &package)
                 .field("source", &source)
                 .finish_non_exhaustive(),
+            Self::Build {
+                package,
+                step,
+                status,
+                ..
+            } => fmt
+                .debug_struct("Build")
+                .field("package", &package)
+                .field("step", &step)
+                .field("status", &status)
+                .finish_non_exhaustive(),
+            Self::MissingArtifact {
+                package,
+                artifact,
+                built,
+            } => fmt
+                .debug_struct("MissingArtifact")
+                .field("package", &package)
+                .field("artifact", &artifact)
+                .field("built", &built)
+                .finish(),
+            Self::Permission { path, .. } => fmt
+                .debug_struct("Permission")
+                .field("path", &path)
+                .finish_non_exhaustive(),
+            Self::Io { path, .. } => fmt
+                .debug_struct("Io")
+                .field("path", &path)
+                .finish_non_exhaustive(),
         }
     }
 }
@@ -95,7 +563,46 @@ impl fmt::Debug for Error {
 impl fmt::Display for Error {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::DeserializeSpec { source, .. } => writeln!(fmt, "invalid yaml in {source}"),
+            Self::Deserialize { kind, source, .. } => {
+                writeln!(fmt, "invalid yaml in {kind} {source}")
+            }
+            Self::InvalidSpec { package, message } => {
+                writeln!(fmt, "invalid spec for {package}: {message}")
+            }
+            Self::UnknownPackage { name, .. } => writeln!(fmt, "unknown package {name}"),
+            Self::DependencyCycle { cycle } => {
+                writeln!(fmt, "dependency cycle: {}", cycle.join(" -> "))
+            }
+            Self::ConflictingPackages { name, repositories } => {
+                writeln!(
+                    fmt,
+                    "{name} is requested from {}",
+                    repositories.join(" and ")
+                )
+            }
+            Self::ConflictingAtoms { atoms } => {
+                writeln!(fmt, "both {} are requested", atoms.join(" and "))
+            }
+            Self::NoLog { package } => writeln!(fmt, "no build log for {package}"),
+            Self::NotInstalled { atom } => writeln!(fmt, "{atom} is not installed"),
+            Self::RequiredBy { atom, dependents } => {
+                writeln!(fmt, "{atom} is required by {}", dependents.join(", "))
+            }
+            Self::Toolchain { tool, message } => writeln!(fmt, "unusable {tool}: {message}"),
+            Self::Jobserver { message } => writeln!(fmt, "jobserver: {message}"),
+            Self::Fetch {
+                package, message, ..
+            } => writeln!(fmt, "failed to fetch {package}: {message}"),
+            Self::Build { package, step, .. } => {
+                writeln!(fmt, "failed to build {package}: {step} failed")
+            }
+            Self::MissingArtifact {
+                package, artifact, ..
+            } => writeln!(fmt, "{package} did not produce {artifact}"),
+            Self::Permission { path, message } => {
+                writeln!(fmt, "permission denied: {path}: {message}")
+            }
+            Self::Io { path, message } => writeln!(fmt, "{path}: {message}"),
         }
     }
 }
@@ -0,0 +1,44 @@
+use {
+    camino::Utf8Path,
+    std::{
+        io,
+        process::{Command, Stdio},
+    },
+};
+
+/// `git` in `dir`, a work tree or a bare repository, without stdin.
+pub fn git(dir: &Utf8Path) -> Command {
+    let mut command = Command::new("git");
+
+    command.current_dir(dir).stdin(Stdio::null());
+    command
+}
+
+/// Run `git` with `args` in `dir`, its output goes to the terminal.
+pub fn run(dir: &Utf8Path, args: &[&str]) -> io::Result<()> {
+    let status = git(dir).args(args).status()?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "`git {}` failed with {status}",
+            args.join(" ")
+        )))
+    }
+}
+
+/// Resolve `revision` of the repository in `dir` to a full commit hash,
+/// `None` if it names no commit.
+pub fn rev_parse(dir: &Utf8Path, revision: &str) -> io::Result<Option<String>> {
+    let output = git(dir)
+        .args(["rev-parse", "--verify", "--quiet", "--end-of-options"])
+        .arg(format!("{revision}^{{commit}}"))
+        .stderr(Stdio::null())
+        .output()?;
+
+    Ok(output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().into()))
+}
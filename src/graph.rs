@@ -0,0 +1,199 @@
+use {
+    super::{package::Package, Atom, Error, Result},
+    milk_target::Target,
+    petgraph::{
+        algo,
+        graph::{DiGraph, NodeIndex},
+        Direction,
+    },
+    std::collections::{HashMap, VecDeque},
+};
+
+/// A package to install.
+pub struct Node<'a> {
+    pub package: &'a Package,
+    /// How the package was requested, dependencies get default features.
+    pub atom: Atom,
+}
+
+/// Dependency graph of the packages to install.
+///
+/// Edges point from a dependency to its dependent.
+pub struct Graph<'a> {
+    graph: DiGraph<Node<'a>, ()>,
+    order: Vec<NodeIndex>,
+}
+
+impl<'a> Graph<'a> {
+    /// Resolve `atoms` and their dependencies against `packages`.
+    ///
+    /// Dependencies are installed for the same target as their dependent.
+    pub fn resolve(packages: &'a [Package], atoms: &[Atom]) -> Result<Self> {
+        let mut graph = DiGraph::new();
+        let mut indices = HashMap::new();
+        let mut queue = VecDeque::new();
+
+        for atom in atoms {
+            let package = find(packages, atom.repository.as_deref(), &atom.package, None)?;
+
+            let index = insert(&mut graph, &mut indices, &mut queue, package, atom.clone())?;
+            let existing = &graph[index].atom;
+
+            // Dependencies take whatever was requested, but two requests must agree.
+            if existing.features != atom.features || existing.reference != atom.reference {
+                return Err(Error::conflicting_atoms(existing, atom));
+            }
+        }
+
+        while let Some(index) = queue.pop_front() {
+            let package = graph[index].package;
+            let target = graph[index].atom.target;
+
+            for dependency in package.dependencies() {
+                // Dependencies may be pinned with `<repository>/<package>`.
+                let (repository, dependency) = match dependency.split_once('/') {
+                    Some((repository, dependency)) => (Some(repository), dependency),
+                    None => (None, dependency.as_str()),
+                };
+
+                let dependency = find(packages, repository, dependency, Some(package.name()))?;
+                let atom = Atom {
+                    repository: None,
+                    package: dependency.name().into(),
+                    features: Vec::new(),
+                    reference: None,
+                    target,
+                };
+
+                let dependency = insert(&mut graph, &mut indices, &mut queue, dependency, atom)?;
+
+                graph.update_edge(dependency, index, ());
+            }
+        }
+
+        let order = algo::toposort(&graph, None)
+            .map_err(|cycle| Error::dependency_cycle(cycle_names(&graph, cycle.node_id())))?;
+
+        Ok(Self { graph, order })
+    }
+
+    /// Iterate node indices in install order, dependencies first.
+    pub fn indices(&self) -> impl Iterator<Item = NodeIndex> + '_ {
+        self.order.iter().copied()
+    }
+
+    pub fn node(&self, index: NodeIndex) -> &Node<'a> {
+        &self.graph[index]
+    }
+
+    /// Number of packages `index` directly depends on.
+    pub fn dependency_count(&self, index: NodeIndex) -> usize {
+        self.graph
+            .neighbors_directed(index, Direction::Incoming)
+            .count()
+    }
+
+    /// Iterate packages that directly depend on `index`.
+    pub fn dependents(&self, index: NodeIndex) -> impl Iterator<Item = NodeIndex> + '_ {
+        self.graph.neighbors_directed(index, Direction::Outgoing)
+    }
+}
+
+/// Find a package by name.
+///
+/// `packages` is ordered by repository priority, so the first match wins.
+fn find<'a>(
+    packages: &'a [Package],
+    repository: Option<&str>,
+    name: &str,
+    required_by: Option<&str>,
+) -> Result<&'a Package> {
+    packages
+        .iter()
+        .filter(|package| repository.is_none_or(|repository| package.repository() == repository))
+        .find(|package| package.name() == name)
+        .ok_or_else(|| match repository {
+            Some(repository) => {
+                Error::unknown_package(&format!("{repository}/{name}"), required_by)
+            }
+            None => Error::unknown_package(name, required_by),
+        })
+}
+
+/// Add `package` for `atom` unless it is in the graph already.
+///
+/// Installs are keyed by package name, so the same name from two
+/// repositories is a conflict.
+fn insert<'a>(
+    graph: &mut DiGraph<Node<'a>, ()>,
+    indices: &mut HashMap<(&'a str, Target), NodeIndex>,
+    queue: &mut VecDeque<NodeIndex>,
+    package: &'a Package,
+    atom: Atom,
+) -> Result<NodeIndex> {
+    let key = (package.name(), atom.target);
+
+    if let Some(&index) = indices.get(&key) {
+        let existing = graph[index].package;
+
+        if existing.repository() != package.repository() {
+            return Err(Error::conflicting_packages(
+                package.name(),
+                existing.repository(),
+                package.repository(),
+            ));
+        }
+
+        return Ok(index);
+    }
+
+    let index = graph.add_node(Node { package, atom });
+
+    indices.insert(key, index);
+    queue.push_back(index);
+
+    Ok(index)
+}
+
+/// Names of the packages forming the cycle through `start`, in dependency order.
+///
+/// The first name is repeated at the end to close the cycle.
+fn cycle_names(graph: &DiGraph<Node<'_>, ()>, start: NodeIndex) -> Vec<Box<str>> {
+    // Breadth-first search along "depends on" edges until `start` is reached again.
+    let mut parents = HashMap::new();
+    let mut queue = VecDeque::from([start]);
+
+    'search: while let Some(index) = queue.pop_front() {
+        for dependency in graph.neighbors_directed(index, Direction::Incoming) {
+            if parents.contains_key(&dependency) {
+                continue;
+            }
+
+            parents.insert(dependency, index);
+
+            if dependency == start {
+                break 'search;
+            }
+
+            queue.push_back(dependency);
+        }
+    }
+
+    let mut cycle = vec![start];
+    let mut index = start;
+
+    while let Some(&parent) = parents.get(&index) {
+        cycle.push(parent);
+        index = parent;
+
+        if index == start {
+            break;
+        }
+    }
+
+    cycle
+        .into_iter()
+        .rev()
+        .map(|index| Box::from(graph[index].package.name()))
+        .collect()
+}
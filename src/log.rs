@@ -0,0 +1,132 @@
+use {
+    super::{root::Root, Error, Result},
+    camino::Utf8PathBuf,
+    milk_cargo::Event,
+    std::{
+        fmt,
+        fs::{self, File, OpenOptions},
+        io::{self, Write},
+        process::Command,
+        time::SystemTime,
+    },
+};
+
+/// Build log of a single install.
+///
+/// Stored at `<root>/log/<package>/<timestamp>.log`. Writing is best effort,
+/// a full disk must not fail an install that would otherwise succeed.
+pub struct Log {
+    file: File,
+}
+
+impl Log {
+    /// Start a new log for `package`.
+    pub fn create(root: &Root, package: &str) -> Result<Self> {
+        let log_dir = root.log_dir().join(package);
+
+        fs::create_dir_all(&log_dir).map_err(|error| Error::io(&log_dir, error))?;
+
+        let timestamp = humantime::format_rfc3339_seconds(SystemTime::now());
+        let path = log_dir.join(format!("{timestamp}.log"));
+        // Appended to, installs within the same second share a log.
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|error| Error::io(&path, error))?;
+
+        Ok(Self { file })
+    }
+
+    /// Find the most recent log of `package`.
+    pub fn latest(root: &Root, package: &str) -> Result<Utf8PathBuf> {
+        let log_dir = root.log_dir().join(package);
+
+        // Timestamps sort chronologically.
+        log_dir
+            .read_dir_utf8()
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.into_path())
+            .filter(|path| path.extension() == Some("log"))
+            .max()
+            .ok_or_else(|| Error::no_log(package))
+    }
+
+    /// Record a command, including its directory and environment.
+    pub fn command(&mut self, command: &Command) {
+        self.line(format_args!("$ {command:?}"));
+    }
+
+    /// Run `command` to completion, failing if it exits unsuccessfully.
+    ///
+    /// Output is captured, stderr becomes part of the error.
+    pub fn run(&mut self, command: &mut Command) -> io::Result<()> {
+        self.command(command);
+
+        let output = command.output()?;
+
+        self.output("stdout", &output.stdout);
+        self.output("stderr", &output.stderr);
+
+        if output.status.success() {
+            return Ok(());
+        }
+
+        let program = command.get_program().to_string_lossy();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        Err(io::Error::other(format!(
+            "`{program}` failed with {}: {}",
+            output.status,
+            stderr.trim()
+        )))
+    }
+
+    /// Record the captured `stream` of a command.
+    pub fn output(&mut self, stream: &str, output: &[u8]) {
+        if output.is_empty() {
+            return;
+        }
+
+        let _ = self.try_output(stream, output);
+    }
+
+    /// Record what cargo reported.
+    pub fn event(&mut self, event: &Event) {
+        match event {
+            Event::Artifact(artifact) => {
+                let fresh = if artifact.fresh { " (fresh)" } else { "" };
+
+                self.line(format_args!(
+                    "compiled {} [{}]{fresh}",
+                    artifact.target,
+                    artifact.kinds.join(", ")
+                ));
+            }
+            Event::Diagnostic(diagnostic) => self.line(diagnostic),
+            Event::BuildScript(script) => {
+                self.line(format_args!("ran build script of {}", script.package_id));
+            }
+            Event::BuildFinished { success } => {
+                self.line(format_args!("build finished, success: {success}"));
+            }
+        }
+    }
+
+    pub fn line<D: fmt::Display>(&mut self, line: D) {
+        let _ = writeln!(self.file, "{line}");
+    }
+
+    fn try_output(&mut self, stream: &str, output: &[u8]) -> io::Result<()> {
+        writeln!(self.file, "--- {stream}")?;
+        self.file.write_all(output)?;
+
+        if !output.ends_with(b"\n") {
+            writeln!(self.file)?;
+        }
+
+        Ok(())
+    }
+}
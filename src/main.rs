@@ -2,9 +2,23 @@ use crate::{artifact::Artifact, atom::Atom, error::Error};
 
 mod artifact;
 mod atom;
+mod cache;
+mod database;
 mod error;
+mod git;
+mod graph;
+mod log;
 mod package;
+mod part;
+mod repository;
+mod root;
+mod scheduler;
+mod source;
+mod sync;
+mod toolchain;
+mod transaction;
 mod tui;
+mod zig;
 
 type Result<T> = std::result::Result<T, Error>;
 
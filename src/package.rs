@@ -1,59 +1,273 @@
 use {
-    super::{Artifact, Error, Result},
-    camino::Utf8Path,
-    milk_cargo::{Cargo, Status},
-    milk_progress::ProgressBars,
-    milk_target::Target,
-    serde::{Deserialize, Serialize},
-    std::{
-        fs,
-        io::{self, BufWriter},
-        os::unix,
-        process::{Command, Stdio},
-        time::Instant,
+    super::{
+        atom::Feature,
+        cache::Cache,
+        database::{InstalledArtifact, Record},
+        log::Log,
+        part::{Backend, BuildOptions, CCppOptions, Context, Outputs, Part, RustOptions},
+        root::Root,
+        source::{Checkout, Source},
+        toolchain::{Toolchain, Zig},
+        transaction::Transaction,
+        zig, Artifact, Atom, Error, Result,
     },
+    camino::{Utf8Path, Utf8PathBuf},
+    jobserver::Client,
+    milk_progress::Progress,
+    milk_spec::PartKind,
+    serde::{Deserialize, Serialize},
+    std::{fs, io},
 };
 
+/// Shared by the installs of one `milk add`.
+#[derive(Clone, Copy)]
+pub struct Environment<'a> {
+    pub root: &'a Root,
+    pub toolchain: &'a Toolchain,
+    pub jobserver: &'a Client,
+    pub progress: &'a Progress,
+    pub cache: &'a Cache,
+}
+
 #[derive(Debug)]
 pub struct Package {
     name: String,
-    serialized: Serialized,
+    repository: String,
+    path: Utf8PathBuf,
+    /// The first one is the primary source.
+    sources: Vec<Source>,
+    /// Tag, branch or commit of the primary source to build instead of its
+    /// default branch.
+    reference: Option<String>,
+    dependencies: Vec<String>,
+    features: Vec<String>,
+    /// In build order.
+    parts: Vec<Part>,
 }
 
+/// The single-source spec format.
 #[derive(Debug, Deserialize, Serialize)]
 struct Serialized {
     source: String,
+    /// Tag, branch or commit to build instead of the default branch.
+    #[serde(default, rename = "ref", skip_serializing_if = "Option::is_none")]
+    reference: Option<String>,
     dependencies: Vec<String>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     features: Vec<String>,
     artifacts: Vec<Artifact>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     beta_artifacts: Vec<(String, Vec<String>)>,
+    /// C and C++ components built with `zig build` before cargo runs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    zig: Vec<zig::Component>,
+    #[serde(default, skip_serializing_if = "BuildOptions::is_default")]
+    build: BuildOptions,
 }
 
+/// The multi-part spec format.
+type MultiPart = milk_spec::Serialized<Artifact>;
+
 impl Package {
     pub fn from_path<P: AsRef<Utf8Path>>(path: P) -> Result<Self> {
         let path = path.as_ref();
-        let name = path.file_stem().unwrap().into();
+        let name: String = path.file_stem().unwrap().into();
+
+        let content = fs::read_to_string(path).map_err(|error| Error::io(path, error))?;
+
+        let repository = path
+            .parent()
+            .and_then(Utf8Path::file_name)
+            .unwrap_or_default()
+            .into();
+
+        let mut package = Self {
+            name,
+            repository,
+            path: path.into(),
+            sources: Vec::new(),
+            reference: None,
+            dependencies: Vec::new(),
+            features: Vec::new(),
+            parts: Vec::new(),
+        };
+
+        if is_multi_part(&package.name, &content)? {
+            let serialized: MultiPart = deserialize(&package.name, &content)?;
+
+            package.load_parts(serialized)?;
+        } else {
+            let serialized: Serialized = deserialize(&package.name, &content)?;
+
+            package.load_legacy(serialized)?;
+        }
 
-        let content = fs::read_to_string(path).unwrap();
-        let serialized: Serialized = serde_yaml::from_str(&content)
-            .map_err(|error| Error::deserialize_spec(Utf8Path::new(&name), &content, error))?;
+        if package.reference.is_some() && !matches!(package.sources[0], Source::Git { .. }) {
+            return Err(Error::invalid_spec(
+                &package.name,
+                "`ref` only applies to git sources",
+            ));
+        }
 
-        Ok(Self { name, serialized })
+        Ok(package)
     }
 
+    /// A spec with `source` becomes a `c_cpp` part for its zig components,
+    /// followed by a `rust` part that installs every artifact.
+    fn load_legacy(&mut self, serialized: Serialized) -> Result<()> {
+        let components = if serialized.zig.is_empty() {
+            zig::from_beta_artifacts(&serialized.beta_artifacts)
+        } else {
+            serialized.zig
+        };
+
+        if !components.is_empty() {
+            self.parts.push(Part {
+                name: PartKind::CCpp.as_str().into(),
+                source: 0,
+                backend: Backend::CCpp(CCppOptions { components }),
+                artifacts: Vec::new(),
+            });
+        }
+
+        self.parts.push(Part {
+            name: PartKind::Rust.as_str().into(),
+            source: 0,
+            backend: Backend::Rust(RustOptions {
+                features: serialized.features.clone(),
+                build: serialized.build,
+            }),
+            artifacts: serialized.artifacts,
+        });
+
+        self.sources = vec![self.parse_source(&serialized.source)?];
+        self.reference = serialized.reference;
+        self.dependencies = serialized.dependencies;
+        self.features = serialized.features;
+
+        Ok(())
+    }
+
+    /// `depends` entries naming a part order the parts, the others are
+    /// dependencies of the package.
+    fn load_parts(&mut self, serialized: MultiPart) -> Result<()> {
+        let name = self.name.clone();
+
+        let sources = serialized.sources;
+
+        if sources.is_empty() {
+            return Err(Error::invalid_spec(&name, "`sources` is empty"));
+        }
+
+        for (index, source) in sources.iter().enumerate() {
+            if sources[..index].contains(source) {
+                let message = format!("source `{source}` is listed twice");
+
+                return Err(Error::invalid_spec(&name, &message));
+            }
+        }
+
+        self.reference = serialized.reference;
+
+        self.sources = sources
+            .iter()
+            .map(|source| self.parse_source(source))
+            .collect::<Result<_>>()?;
+
+        let names = serialized
+            .parts
+            .iter()
+            .map(|part| part.name().to_string())
+            .collect::<Vec<_>>();
+
+        let order = part_order(&serialized.parts, &names)
+            .map_err(|cycle| Error::dependency_cycle(qualify(&name, cycle)))?;
+
+        let mut parts = serialized.parts.into_iter().map(Some).collect::<Vec<_>>();
+
+        for index in order {
+            let part = parts[index].take().unwrap();
+            let part_name = names[index].clone();
+
+            let source = match &part.source {
+                Some(source) => sources
+                    .iter()
+                    .position(|known| known == source)
+                    .ok_or_else(|| {
+                        let message = format!("part `{part_name}` uses unknown source `{source}`");
+
+                        Error::invalid_spec(&name, &message)
+                    })?,
+                None if sources.len() == 1 => 0,
+                None => {
+                    let message = format!("part `{part_name}` must name one of the sources");
+
+                    return Err(Error::invalid_spec(&name, &message));
+                }
+            };
+
+            for dependency in &part.depends {
+                if !names.contains(dependency) && !self.dependencies.contains(dependency) {
+                    self.dependencies.push(dependency.clone());
+                }
+            }
+
+            let backend = backend(part.uses, part.with).map_err(|message| {
+                Error::invalid_spec(&name, &format!("`with` of part `{part_name}`: {message}"))
+            })?;
+
+            if let Backend::Rust(RustOptions { features, .. }) = &backend {
+                for feature in features {
+                    if !self.features.contains(feature) {
+                        self.features.push(feature.clone());
+                    }
+                }
+            }
+
+            self.parts.push(Part {
+                name: part_name,
+                source,
+                backend,
+                artifacts: part.artifacts,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn parse_source(&self, source: &str) -> Result<Source> {
+        let spec_dir = self.path.parent().unwrap_or(Utf8Path::new("."));
+
+        Source::parse(source, spec_dir).map_err(|message| {
+            Error::invalid_spec(self.name(), &format!("source `{source}`: {message}"))
+        })
+    }
+
+    /// Rewrite the spec at `path` in canonical form.
     pub fn format<P: AsRef<Utf8Path>>(path: P) -> Result<()> {
         let path = path.as_ref();
         let name = path.file_stem().unwrap();
 
-        let content = fs::read_to_string(path).unwrap();
-        let serialized: Serialized = serde_yaml::from_str(&content)
-            .map_err(|error| Error::deserialize_spec(Utf8Path::new(&name), &content, error))?;
+        let content = fs::read_to_string(path).map_err(|error| Error::io(path, error))?;
 
-        let serialized = serde_yaml::to_string(&serialized).unwrap();
+        let serialized = if is_multi_part(name, &content)? {
+            let serialized: MultiPart = deserialize(name, &content)?;
 
-        fs::write(path, serialized).unwrap();
+            serde_yaml::to_string(&serialized).unwrap()
+        } else {
+            let mut serialized: Serialized = deserialize(name, &content)?;
+
+            // `beta_artifacts` is ignored once `zig` is set.
+            if serialized.zig.is_empty() {
+                serialized.zig = zig::from_beta_artifacts(&serialized.beta_artifacts);
+            }
+
+            serialized.beta_artifacts.clear();
+
+            serde_yaml::to_string(&serialized).unwrap()
+        };
+
+        fs::write(path, serialized).map_err(|error| Error::io(path, error))?;
 
         Ok(())
     }
@@ -62,213 +276,366 @@ impl Package {
         &self.name
     }
 
-    pub fn source(&self) -> &str {
-        &self.serialized.source
+    /// Set the name of the repository this package belongs to.
+    pub fn with_repository(mut self, repository: &str) -> Self {
+        self.repository = repository.into();
+        self
+    }
+
+    pub fn repository(&self) -> &str {
+        &self.repository
+    }
+
+    pub fn spec(&self) -> &Utf8Path {
+        &self.path
+    }
+
+    pub fn sources(&self) -> &[Source] {
+        &self.sources
+    }
+
+    pub fn reference(&self) -> Option<&str> {
+        self.reference.as_deref()
     }
 
     pub fn dependencies(&self) -> &[String] {
-        &self.serialized.dependencies
+        &self.dependencies
     }
 
+    /// Features of the spec, of every `rust` part for multi-part specs.
     pub fn features(&self) -> &[String] {
-        &self.serialized.features
+        &self.features
     }
 
-    pub fn artifacts(&self) -> &[Artifact] {
-        &self.serialized.artifacts
+    /// Features of the spec with `changes` applied.
+    pub fn features_with(&self, changes: &[Feature]) -> Vec<String> {
+        Feature::apply(changes, self.features())
     }
 
-    pub async fn install(&self, target: Target) -> io::Result<()> {
-        let rust_triple = target.rust_triple();
-        let root_dir = Utf8Path::new("/mocha");
-        let source_dir = root_dir.join("src").join(self.name());
-        let target_dir = source_dir.join(format!("target/{rust_triple}/release"));
-        let binary_dir = root_dir.join("bin");
-
-        print!(" sync {}.. ", self.name());
+    pub fn parts(&self) -> &[Part] {
+        &self.parts
+    }
 
-        let instant = Instant::now();
-        let mut command = Command::new("git");
+    /// What the parts need of zig.
+    pub fn zig(&self) -> Zig {
+        self.parts
+            .iter()
+            .map(Part::zig)
+            .max()
+            .unwrap_or(Zig::Unused)
+    }
 
-        if source_dir.exists() {
-            command.arg("fetch").args(&["--depth", "1"]);
-        } else {
-            fs::create_dir(&source_dir)?;
+    /// Artifacts of every part.
+    pub fn artifacts(&self) -> Vec<&Artifact> {
+        self.parts.iter().flat_map(|part| &part.artifacts).collect()
+    }
 
-            command
-                .arg("clone")
-                .args(&["--depth", "1"])
-                .arg(self.source())
-                .arg(".");
+    /// Install the package for `atom`, replacing the `previous` files of an
+    /// earlier install.
+    pub async fn install(
+        &self,
+        environment: &Environment<'_>,
+        atom: &Atom,
+        previous: &[Utf8PathBuf],
+    ) -> Result<Record> {
+        let Environment {
+            root,
+            toolchain,
+            jobserver,
+            progress,
+            cache,
+        } = *environment;
+        let features = self.features_with(&atom.features);
+        let reference = atom.reference.as_deref().or(self.reference());
+
+        let mut log = Log::create(root, self.name())?;
+
+        progress.phase(self.name(), "fetch");
+
+        let (source_dirs, revisions) = self
+            .checkout(root, cache, reference, &mut log)?
+            .into_iter()
+            .map(|Checkout { dir, revision }| (dir, revision))
+            .unzip::<_, _, Vec<_>, Vec<_>>();
+
+        let context = Context {
+            package: self.name(),
+            toolchain,
+            jobserver,
+            progress,
+            atom,
+            offline: cache.is_offline(),
+        };
+
+        let mut outputs = Outputs::default();
+
+        for part in self.parts() {
+            progress.phase(self.name(), &part.phase());
+
+            part.build(&context, &source_dirs[part.source], &mut log, &mut outputs)
+                .await?;
         }
 
-        command
-            .current_dir(&source_dir)
-            /*.stdin(Stdio::null())
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())*/
-            ;
+        progress.phase(self.name(), "install");
 
-        println!("!!! {command:?}");
+        let mut installed = Vec::new();
+        let mut transaction = Transaction::new(root.staging_dir().join(self.name()))?;
 
-        command.spawn()?.wait()?;
+        for part in self.parts() {
+            let source_dir = &source_dirs[part.source];
 
-        println!("done! took {:.2?}", instant.elapsed());
+            for artifact in &part.artifacts {
+                let destination = artifact.destination(root, self.name());
+
+                if let Some(dir) = destination.parent() {
+                    fs::create_dir_all(dir).map_err(|error| Error::io(dir, error))?;
+                }
 
-        let instant = Instant::now();
+                if let Artifact::Sym { points_to, .. } = artifact {
+                    transaction.symlink(points_to, destination.clone())?;
+                } else {
+                    let src_path =
+                        part.locate(artifact, source_dir, &outputs).ok_or_else(|| {
+                            Error::missing_artifact(self.name(), artifact, outputs.built(artifact))
+                        })?;
 
-        print!(" build {}.. ", self.name());
+                    transaction.copy(&src_path, destination.clone())?;
+                }
 
-        use std::io::Write;
+                installed.push(InstalledArtifact {
+                    artifact: artifact.clone(),
+                    files: vec![destination],
+                });
+            }
+        }
 
-        let mut build = std::fs::File::options()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(source_dir.join("build.zig"))?;
+        // Files of the previous install this one no longer produces.
+        let stale = previous
+            .iter()
+            .filter(|file| {
+                installed
+                    .iter()
+                    .all(|installed| !installed.files.contains(file))
+            })
+            .collect::<Vec<_>>();
+
+        for file in &stale {
+            transaction.remove((*file).clone());
+        }
 
-        writeln!(&mut build, "const std = @import(\"std\");")?;
-        writeln!(&mut build)?;
-        writeln!(&mut build, "pub fn build(b: *std.Build) void {{")?;
-        writeln!(
-            &mut build,
-            "    const optimize = b.standardOptimizeOption(.{{}});"
-        )?;
-        writeln!(
-            &mut build,
-            "    const target = b.standardTargetOptions(.{{}});"
-        )?;
-        writeln!(&mut build)?;
+        transaction.commit()?;
 
-        for (artifact, sources) in self.serialized.beta_artifacts.iter() {
-            let sources = sources
+        for file in stale {
+            progress.println(artifact_line("rm", file.as_str(), None));
+        }
+
+        for InstalledArtifact { artifact, files } in &installed {
+            let line = match artifact {
+                Artifact::Bin { name, rename_to } => {
+                    artifact_line("bin", name, rename_to.as_deref())
+                }
+                Artifact::Sym { name, points_to } => artifact_line("sym", points_to, Some(name)),
+                Artifact::Lib { kind, name, .. } => {
+                    artifact_line("lib", &kind.file_name(name), Some(files[0].as_str()))
+                }
+                Artifact::Header { path, .. }
+                | Artifact::Man { path, .. }
+                | Artifact::Data { path, .. }
+                | Artifact::Completion { path, .. } => {
+                    artifact_line(artifact.kind(), path, Some(files[0].as_str()))
+                }
+            };
+
+            progress.println(line);
+        }
+
+        Ok(Record {
+            atom: Atom {
+                repository: None,
+                ..atom.clone()
+            },
+            repository: self.repository.clone(),
+            spec: self.spec().into(),
+            source: self
+                .sources()
                 .iter()
-                .map(|source| format!("\"{source}\""))
+                .map(Source::to_string)
                 .collect::<Vec<_>>()
-                .join(",\n        ");
-
-            if let Some(artifact) = artifact.strip_prefix("lib ") {
-                writeln!(
-                    &mut build,
-                    "    const lib{artifact} = b.addStaticLibrary(.{{"
-                )?;
-                writeln!(&mut build, "        .link_libc = true,")?;
-                writeln!(&mut build, "        .name = \"{artifact}\",")?;
-                writeln!(&mut build, "        .optimize = optimize,")?;
-                writeln!(&mut build, "        .target = target,")?;
-                writeln!(&mut build, "    }});")?;
-                writeln!(&mut build)?;
-                writeln!(&mut build, "    lib{artifact}.addCSourceFiles(&.{{")?;
-                writeln!(&mut build, "        {sources}")?;
-                writeln!(&mut build, "        }},")?;
-                writeln!(&mut build, "        &[_][]const u8{{}},")?;
-                writeln!(&mut build, "    );")?;
-                writeln!(&mut build, "    lib{artifact}.addIncludePath(\"lib\");")?;
-                writeln!(
-                    &mut build,
-                    "    lib{artifact}.addIncludePath(\"lib/common\");"
-                )?;
-                writeln!(&mut build)?;
-                writeln!(&mut build, "    b.installArtifact(lib{artifact});")?;
-                writeln!(&mut build)?;
-            }
+                .join(", "),
+            reference: reference.map(String::from),
+            revision: revisions.join(", "),
+            features,
+            dependencies: self.dependencies().to_vec(),
+            artifacts: installed,
+        })
+    }
 
-            if let Some(artifact) = artifact.strip_prefix("bin ") {
-                writeln!(&mut build, "    const {artifact} = b.addExecutable(.{{")?;
-                writeln!(&mut build, "        .link_libc = true,")?;
-                writeln!(&mut build, "        .name = \"{artifact}\",")?;
-                writeln!(&mut build, "        .optimize = optimize,")?;
-                writeln!(&mut build, "        .target = target,")?;
-                writeln!(&mut build, "    }});")?;
-                writeln!(&mut build)?;
-                writeln!(&mut build, "    {artifact}.addCSourceFiles(&.{{")?;
-                writeln!(&mut build, "        {sources}")?;
-                writeln!(&mut build, "        }},")?;
-                writeln!(&mut build, "        &[_][]const u8{{}},")?;
-                writeln!(&mut build, "    );")?;
-                writeln!(&mut build, "    {artifact}.addIncludePath(\"lib\");")?;
-                writeln!(&mut build, "    {artifact}.addIncludePath(\"lib/common\");")?;
-                writeln!(
-                    &mut build,
-                    "    {artifact}.addObjectFile(\"zig-out/lib/libzstd.a\");"
-                )?;
-                writeln!(&mut build)?;
-                writeln!(&mut build, "    b.installArtifact({artifact});")?;
-                writeln!(&mut build)?;
-            }
+    /// Fetch the sources of the package into `cache`, along with whatever
+    /// its parts download when built, so `atom` can be installed offline.
+    pub fn fetch(&self, root: &Root, cache: &Cache, cargo: &Utf8Path, atom: &Atom) -> Result<()> {
+        let reference = atom.reference.as_deref().or(self.reference());
+
+        let mut log = Log::create(root, self.name())?;
+        let checkouts = self.checkout(root, cache, reference, &mut log)?;
+
+        for source in self.sources() {
+            artifact_log("fetch", &source.to_string(), None);
         }
 
-        writeln!(&mut build, "}}")?;
+        for part in self.parts() {
+            part.fetch(
+                self.name(),
+                cargo,
+                &checkouts[part.source].dir,
+                atom,
+                &mut log,
+            )?;
+        }
 
-        let mut command = Command::new("zig");
+        Ok(())
+    }
 
-        command
-            .arg("build")
-            .arg("-Doptimize=ReleaseFast")
-            .arg("-Dtarget=x86_64-linux-musl")
-            .current_dir(&source_dir);
+    /// Check out every source from `cache`, in order.
+    ///
+    /// `reference` only applies to the primary source.
+    fn checkout(
+        &self,
+        root: &Root,
+        cache: &Cache,
+        reference: Option<&str>,
+        log: &mut Log,
+    ) -> Result<Vec<Checkout>> {
+        self.sources()
+            .iter()
+            .enumerate()
+            .map(|(index, source)| {
+                let reference = reference.filter(|_| index == 0);
+
+                source
+                    .fetch(&root.source_dir(self.name(), index), reference, cache, log)
+                    .map_err(|error| Error::fetch(self.name(), &source.to_string(), error))
+            })
+            .collect()
+    }
+}
 
-        println!("!!! {command:?}");
+/// Whether `content` is a multi-part spec, which has `parts`.
+fn is_multi_part(name: &str, content: &str) -> Result<bool> {
+    let value: serde_yaml::Value = deserialize(name, content)?;
 
-        command.spawn()?.wait()?;
+    Ok(value.get("parts").is_some())
+}
 
-        let cargo = Cargo::new("~/.cargo/bin/cargo")?;
-        let mut child = cargo
-            .build(&source_dir)
-            .features(self.features())
-            .target(target)
-            .spawn()?;
+fn deserialize<T: serde::de::DeserializeOwned>(name: &str, content: &str) -> Result<T> {
+    serde_yaml::from_str(content)
+        .map_err(|error| Error::deserialize("spec", Utf8Path::new(name), content, error))
+}
 
-        let mut stdout = BufWriter::new(io::stdout());
+/// Create the backend of a `uses` part from its `with` options.
+fn backend(uses: PartKind, with: serde_yaml::Mapping) -> std::result::Result<Backend, String> {
+    if matches!(uses, PartKind::Zig | PartKind::Copy) && !with.is_empty() {
+        return Err(format!("`{uses}` takes no options"));
+    }
 
-        while let Some(Status { completed, total }) = child.process().await? {
-            ProgressBars::new()
-                .add(self.name(), completed, total)
-                .render(&mut stdout)?;
-        }
+    let with = serde_yaml::Value::Mapping(with);
 
-        println!("done! took {:.2?}", instant.elapsed());
+    let backend = match uses {
+        PartKind::Rust => Backend::Rust(options(with)?),
+        PartKind::CCpp => Backend::CCpp(options(with)?),
+        PartKind::Zig => Backend::Zig,
+        PartKind::Copy => Backend::Copy,
+    };
 
-        for arifact in self.artifacts() {
-            match arifact {
-                Artifact::Bin { name, rename_to } => {
-                    let src_name: &str = name;
-                    let src_path = target_dir.join(src_name);
-                    let dst_name = rename_to.as_deref().unwrap_or(src_name);
-                    let dst_path = binary_dir.join(dst_name);
+    Ok(backend)
+}
 
-                    let _ = fs::remove_file(&dst_path);
-                    fs::copy(src_path, dst_path)?;
+fn options<T: serde::de::DeserializeOwned>(
+    with: serde_yaml::Value,
+) -> std::result::Result<T, String> {
+    serde_yaml::from_value(with).map_err(|error| error.to_string())
+}
 
-                    artifact_log("bin", src_name, rename_to.as_deref());
-                }
+/// Order `parts` so each comes after the parts it depends on.
+///
+/// On a cycle, the names of the parts forming it are returned.
+fn part_order<A>(
+    parts: &[milk_spec::Part<A>],
+    names: &[String],
+) -> std::result::Result<Vec<usize>, Vec<String>> {
+    fn visit<A>(
+        index: usize,
+        parts: &[milk_spec::Part<A>],
+        names: &[String],
+        stack: &mut Vec<usize>,
+        order: &mut Vec<usize>,
+    ) -> std::result::Result<(), Vec<String>> {
+        if order.contains(&index) {
+            return Ok(());
+        }
 
-                Artifact::Sym { name, points_to } => {
-                    let src_name: &str = points_to;
-                    let dst_name: &str = name;
-                    let dst_path = binary_dir.join(dst_name);
+        if let Some(position) = stack.iter().position(|&visiting| visiting == index) {
+            let mut cycle = stack[position..]
+                .iter()
+                .map(|&index| names[index].clone())
+                .collect::<Vec<_>>();
 
-                    let _ = fs::remove_file(&dst_path);
-                    unix::fs::symlink(src_name, dst_path)?;
+            cycle.push(names[index].clone());
 
-                    artifact_log("sym", src_name, Some(dst_name));
-                }
+            return Err(cycle);
+        }
+
+        stack.push(index);
+
+        for dependency in &parts[index].depends {
+            for (dependency, _) in names
+                .iter()
+                .enumerate()
+                .filter(|(_, name)| *name == dependency)
+            {
+                visit(dependency, parts, names, stack, order)?;
             }
         }
 
+        stack.pop();
+        order.push(index);
+
         Ok(())
     }
+
+    let mut order = Vec::new();
+
+    for index in 0..parts.len() {
+        visit(index, parts, names, &mut Vec::new(), &mut order)?;
+    }
+
+    Ok(order)
+}
+
+/// Prefix part names with their package, for error messages.
+fn qualify(package: &str, names: Vec<String>) -> Vec<Box<str>> {
+    names
+        .into_iter()
+        .map(|name| format!("{package}:{name}").into_boxed_str())
+        .collect()
+}
+
+pub(crate) fn build_error(package: &str, step: &str, error: io::Error) -> Error {
+    Error::build(package, step, None, Some(&error.to_string()), "")
+}
+
+pub(crate) fn artifact_log(kind: &'static str, source_name: &str, destination_name: Option<&str>) {
+    println!("{}", artifact_line(kind, source_name, destination_name));
 }
 
-fn artifact_log(kind: &'static str, source_name: &str, destination_name: Option<&str>) {
+fn artifact_line(kind: &'static str, source_name: &str, destination_name: Option<&str>) -> String {
     use yansi::{Color, Style};
 
     let kind_style = Style::new(Color::Black).bg(Color::Green);
     let kind = kind_style.paint(format!(" {kind} "));
 
     if let Some(destination_name) = destination_name {
-        println!(" {kind} {source_name} -> {destination_name}");
+        format!(" {kind} {source_name} -> {destination_name}")
     } else {
-        println!(" {kind} {source_name}");
+        format!(" {kind} {source_name}")
     }
 }
@@ -0,0 +1,440 @@
+use {
+    super::{
+        atom::Feature,
+        log::Log,
+        package::build_error,
+        toolchain::{Toolchain, Zig},
+        zig, Artifact, Atom, Error, Result,
+    },
+    camino::{Utf8Path, Utf8PathBuf},
+    jobserver::Client,
+    milk_cargo::{Build, Cargo, Event, Level, Status},
+    milk_progress::Progress,
+    milk_spec::PartKind,
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::BTreeMap,
+        fs, io,
+        process::{Command, Stdio},
+    },
+};
+
+/// Something of a package built with one backend.
+#[derive(Debug)]
+pub struct Part {
+    pub name: String,
+    /// Index into the sources of the package.
+    pub source: usize,
+    pub backend: Backend,
+    pub artifacts: Vec<Artifact>,
+}
+
+#[derive(Debug)]
+pub enum Backend {
+    /// `cargo build`.
+    Rust(RustOptions),
+    /// A generated `build.zig` for C and C++ components.
+    CCpp(CCppOptions),
+    /// The `build.zig` of the source.
+    Zig,
+    /// Nothing to build, artifacts are files of the source.
+    Copy,
+}
+
+/// Options of a `rust` part.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RustOptions {
+    pub features: Vec<String>,
+    #[serde(flatten)]
+    pub build: BuildOptions,
+}
+
+/// Options of a `c_cpp` part.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct CCppOptions {
+    pub components: Vec<zig::Component>,
+}
+
+/// How cargo builds the package.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct BuildOptions {
+    /// Rustup toolchain, `null` to use whichever cargo picks.
+    toolchain: Option<String>,
+    /// Use `cargo zigbuild` instead of `cargo build`.
+    zigbuild: bool,
+    profile: String,
+    default_features: bool,
+    locked: bool,
+    frozen: bool,
+    offline: bool,
+    /// Binaries to build, all of them if empty.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    bins: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    rustflags: Vec<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    env: BTreeMap<String, String>,
+}
+
+/// What the parts of a package built, by file name.
+#[derive(Default)]
+pub struct Outputs {
+    executables: BTreeMap<String, Utf8PathBuf>,
+    libraries: BTreeMap<String, Utf8PathBuf>,
+}
+
+/// Shared by the parts of a package.
+pub struct Context<'a> {
+    pub package: &'a str,
+    pub toolchain: &'a Toolchain,
+    pub jobserver: &'a Client,
+    pub progress: &'a Progress,
+    pub atom: &'a Atom,
+    /// Build without network access, from what `milk fetch` downloaded.
+    pub offline: bool,
+}
+
+impl Part {
+    pub fn kind(&self) -> PartKind {
+        match self.backend {
+            Backend::Rust(_) => PartKind::Rust,
+            Backend::CCpp(_) => PartKind::CCpp,
+            Backend::Zig => PartKind::Zig,
+            Backend::Copy => PartKind::Copy,
+        }
+    }
+
+    /// What the part needs of zig.
+    pub fn zig(&self) -> Zig {
+        match &self.backend {
+            Backend::Rust(options) if options.build.zigbuild => Zig::Any,
+            Backend::Rust(_) | Backend::Copy => Zig::Unused,
+            Backend::CCpp(_) => Zig::Generated,
+            Backend::Zig => Zig::Any,
+        }
+    }
+
+    /// The command that builds the part, as named in build errors.
+    pub fn step(&self) -> &'static str {
+        match self.backend {
+            Backend::Rust(_) => "cargo build",
+            Backend::CCpp(_) | Backend::Zig => "zig build",
+            Backend::Copy => "copy",
+        }
+    }
+
+    /// The progress phase of the part, parts named after their backend
+    /// show just the step.
+    pub fn phase(&self) -> String {
+        if self.name == self.kind().as_str() {
+            self.step().into()
+        } else {
+            format!("{} {}", self.step(), self.name)
+        }
+    }
+
+    /// Build the part in `source_dir`, recording what was built in `outputs`.
+    pub async fn build(
+        &self,
+        context: &Context<'_>,
+        source_dir: &Utf8Path,
+        log: &mut Log,
+        outputs: &mut Outputs,
+    ) -> Result<()> {
+        match &self.backend {
+            Backend::Rust(options) => {
+                let features = Feature::apply(&context.atom.features, &options.features);
+
+                cargo_build(context, source_dir, &features, &options.build, log, outputs).await
+            }
+            Backend::CCpp(CCppOptions { components }) => {
+                let components = zig::expand(components, source_dir)
+                    .map_err(|error| build_error(context.package, "zig build", error))?;
+
+                let build_path = source_dir.join("build.zig");
+
+                fs::write(&build_path, zig::render(&components))
+                    .map_err(|error| Error::io(&build_path, error))?;
+
+                zig_build(context, source_dir, log, outputs)
+            }
+            Backend::Zig => zig_build(context, source_dir, log, outputs),
+            Backend::Copy => Ok(()),
+        }
+    }
+
+    /// Download what building the part in `source_dir` needs besides its
+    /// source, that is the crates of `rust` parts.
+    pub fn fetch(
+        &self,
+        package: &str,
+        cargo: &Utf8Path,
+        source_dir: &Utf8Path,
+        atom: &Atom,
+        log: &mut Log,
+    ) -> Result<()> {
+        let Backend::Rust(options) = &self.backend else {
+            return Ok(());
+        };
+
+        let cargo =
+            Cargo::new(cargo).map_err(|error| Error::toolchain("cargo", &error.to_string()))?;
+
+        let build = cargo.build(source_dir).target(atom.target);
+
+        log.run(&mut options.build.apply(build).fetch())
+            .map_err(|error| build_error(package, "cargo fetch", error))
+    }
+
+    /// Find the file to install for `artifact`, symlinks have none.
+    pub fn locate(
+        &self,
+        artifact: &Artifact,
+        source_dir: &Utf8Path,
+        outputs: &Outputs,
+    ) -> Option<Utf8PathBuf> {
+        match (artifact, &self.backend) {
+            (Artifact::Bin { name, .. }, Backend::Copy) => existing(source_dir.join(&**name)),
+            (Artifact::Bin { name, .. }, _) => outputs.executables.get(&**name).cloned(),
+            (Artifact::Lib { kind, name, .. }, Backend::Copy) => {
+                existing(source_dir.join(kind.file_name(name)))
+            }
+            (Artifact::Lib { kind, name, .. }, _) => {
+                outputs.libraries.get(&kind.file_name(name)).cloned()
+            }
+            (Artifact::Sym { .. }, _) => None,
+            // Missing files are reported when they are copied.
+            (
+                Artifact::Header { path, .. }
+                | Artifact::Man { path, .. }
+                | Artifact::Data { path, .. }
+                | Artifact::Completion { path, .. },
+                _,
+            ) => Some(source_dir.join(&**path)),
+        }
+    }
+}
+
+impl Outputs {
+    /// File names of what was built of the same kind as `artifact`.
+    pub fn built(&self, artifact: &Artifact) -> Vec<&str> {
+        let built = match artifact {
+            Artifact::Bin { .. } => &self.executables,
+            Artifact::Lib { .. } => &self.libraries,
+            _ => return Vec::new(),
+        };
+
+        built.keys().map(String::as_str).collect()
+    }
+}
+
+impl BuildOptions {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn apply(&self, build: Build) -> Build {
+        let mut build = build
+            .toolchain(self.toolchain.as_deref())
+            .zigbuild(self.zigbuild)
+            .profile(&self.profile)
+            .default_features(self.default_features)
+            .locked(self.locked)
+            .frozen(self.frozen)
+            .offline(self.offline)
+            .bins(&self.bins)
+            .rustflags(&self.rustflags);
+
+        for (key, value) in &self.env {
+            build = build.env(key, value);
+        }
+
+        build
+    }
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        Self {
+            toolchain: Some(String::from("nightly")),
+            zigbuild: true,
+            profile: String::from("release"),
+            default_features: false,
+            locked: false,
+            frozen: false,
+            offline: false,
+            bins: Vec::new(),
+            rustflags: Vec::new(),
+            env: BTreeMap::new(),
+        }
+    }
+}
+
+async fn cargo_build(
+    context: &Context<'_>,
+    source_dir: &Utf8Path,
+    features: &[String],
+    options: &BuildOptions,
+    log: &mut Log,
+    outputs: &mut Outputs,
+) -> Result<()> {
+    let package = context.package;
+
+    let cargo = Cargo::new(&context.toolchain.cargo)
+        .map_err(|error| Error::toolchain("cargo", &error.to_string()))?;
+
+    let mut build = cargo
+        .build(source_dir)
+        .features(features)
+        .target(context.atom.target)
+        .jobserver(context.jobserver.clone());
+
+    if let Some(zig) = &context.toolchain.zig {
+        build = build.zig(zig);
+    }
+
+    let mut build = options.apply(build);
+
+    if context.offline {
+        build = build.offline(true);
+    }
+
+    let mut child = build
+        .spawn()
+        .map_err(|error| build_error(package, "cargo build", error))?;
+
+    log.line(format_args!("$ {}", child.command()));
+
+    while let Some(event) = child
+        .process()
+        .await
+        .map_err(|error| build_error(package, "cargo build", error))?
+    {
+        log.event(&event);
+
+        match event {
+            Event::Artifact(artifact) => {
+                if let Some(executable) = artifact.executable {
+                    if artifact.kinds.iter().any(|kind| kind == "bin") {
+                        outputs.executables.insert(artifact.target, executable);
+                    }
+                } else if artifact
+                    .kinds
+                    .iter()
+                    .any(|kind| kind == "staticlib" || kind == "cdylib")
+                {
+                    for filename in artifact.filenames {
+                        if let Some(file_name) = filename.file_name() {
+                            outputs.libraries.insert(file_name.into(), filename.clone());
+                        }
+                    }
+                }
+            }
+            // Errors are reported once the build fails.
+            Event::Diagnostic(diagnostic) if diagnostic.level == Level::Warning => {
+                context.progress.println(diagnostic);
+            }
+            _ => {}
+        }
+
+        let Status { completed, total } = child.status();
+
+        context.progress.update(package, completed, total);
+    }
+
+    let finished = child
+        .wait()
+        .await
+        .map_err(|error| build_error(package, "cargo build", error))?;
+
+    log.output("stderr", finished.stderr.as_bytes());
+
+    if !finished.status.success() {
+        let errors = finished.errors.join("\n");
+
+        return Err(Error::build(
+            package,
+            "cargo build",
+            Some(finished.status),
+            (!errors.is_empty()).then_some(errors.as_str()),
+            &finished.stderr,
+        ));
+    }
+
+    Ok(())
+}
+
+/// Run the `build.zig` in `source_dir`.
+fn zig_build(
+    context: &Context<'_>,
+    source_dir: &Utf8Path,
+    log: &mut Log,
+    outputs: &mut Outputs,
+) -> Result<()> {
+    let package = context.package;
+    let zig_out = source_dir.join("zig-out");
+
+    // Left over from a previous build, it would be mistaken for this one's.
+    match fs::remove_dir_all(&zig_out) {
+        Ok(()) => {}
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+        Err(error) => return Err(Error::io(&zig_out, error)),
+    }
+
+    let mut command = Command::new(context.toolchain.zig()?);
+
+    // Zig does not take part in the jobserver, so it is limited to the
+    // token this build holds.
+    command
+        .arg("build")
+        .arg("-j1")
+        .arg("-Doptimize=ReleaseFast")
+        .arg(format!("-Dtarget={}", context.atom.target.zig_triple()))
+        .current_dir(source_dir);
+
+    log.command(&command);
+
+    let output = command
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|error| build_error(package, "zig build", error))?;
+
+    log.output("stdout", &output.stdout);
+    log.output("stderr", &output.stderr);
+
+    if !output.status.success() {
+        let log = String::from_utf8_lossy(&output.stderr);
+
+        return Err(Error::build(
+            package,
+            "zig build",
+            Some(output.status),
+            None,
+            &log,
+        ));
+    }
+
+    outputs.executables.extend(files(&zig_out.join("bin")));
+    outputs.libraries.extend(files(&zig_out.join("lib")));
+
+    Ok(())
+}
+
+/// Files in `dir` by name, nothing if it does not exist.
+fn files(dir: &Utf8Path) -> Vec<(String, Utf8PathBuf)> {
+    dir.read_dir_utf8()
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+        .map(|entry| (entry.file_name().into(), entry.into_path()))
+        .collect()
+}
+
+/// `path`, if it exists.
+fn existing(path: Utf8PathBuf) -> Option<Utf8PathBuf> {
+    path.is_file().then_some(path)
+}
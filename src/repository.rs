@@ -0,0 +1,133 @@
+use {
+    super::{package::Package, root::Root, Error, Result},
+    camino::{Utf8Path, Utf8PathBuf},
+    serde::{Deserialize, Serialize},
+    std::{cmp::Reverse, fs, io},
+};
+
+/// Repository configuration.
+///
+/// Stored as YAML at `<root>/repos.yaml`. Without it, every directory
+/// in `<root>/repos` is used as a repository.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Config {
+    #[serde(default)]
+    repositories: Vec<Repository>,
+}
+
+/// A package repository.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Repository {
+    pub name: String,
+    /// Where to clone the repository from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Location of the repository, defaults to `<root>/repos/<name>`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<Utf8PathBuf>,
+    /// Repositories with a higher priority win when packages share a name.
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+}
+
+impl Config {
+    /// Load the configuration from `root`.
+    pub fn load(root: &Root) -> Result<Self> {
+        let path = root.path().join("repos.yaml");
+
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                return Ok(Self::discover(&root.repos_dir()))
+            }
+            Err(error) => return Err(Error::io(&path, error)),
+        };
+
+        serde_yaml::from_str(&content)
+            .map_err(|error| Error::deserialize("repository config", &path, &content, error))
+    }
+
+    /// Use every directory in `repos_dir` as a repository.
+    fn discover(repos_dir: &Utf8Path) -> Self {
+        let mut repositories = repos_dir
+            .read_dir_utf8()
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+            .map(|entry| Repository {
+                name: entry.file_name().into(),
+                url: None,
+                path: Some(entry.into_path()),
+                priority: 0,
+                enabled: true,
+            })
+            .collect::<Vec<_>>();
+
+        repositories.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Self { repositories }
+    }
+
+    /// Iterate enabled repositories, highest priority first.
+    ///
+    /// Repositories of equal priority keep their configured order.
+    pub fn repositories(&self) -> Vec<&Repository> {
+        let mut repositories = self
+            .repositories
+            .iter()
+            .filter(|repository| repository.enabled)
+            .collect::<Vec<_>>();
+
+        repositories.sort_by_key(|repository| Reverse(repository.priority));
+        repositories
+    }
+
+    /// Load the packages of all enabled repositories.
+    ///
+    /// Packages from higher priority repositories come first.
+    pub fn packages(&self, root: &Root) -> Result<Vec<Package>> {
+        let mut packages = Vec::new();
+
+        for repository in self.repositories() {
+            packages.extend(repository.packages(root)?);
+        }
+
+        Ok(packages)
+    }
+}
+
+impl Repository {
+    pub fn path(&self, root: &Root) -> Utf8PathBuf {
+        self.path
+            .clone()
+            .unwrap_or_else(|| root.repos_dir().join(&self.name))
+    }
+
+    /// Load every spec in the repository.
+    pub fn packages(&self, root: &Root) -> Result<Vec<Package>> {
+        let path = self.path(root);
+
+        let mut paths = path
+            .read_dir_utf8()
+            .and_then(|entries| entries.collect::<io::Result<Vec<_>>>())
+            .map_err(|error| Error::io(&path, error))?
+            .into_iter()
+            .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+            .map(|entry| entry.into_path())
+            .collect::<Vec<_>>();
+
+        paths.sort();
+
+        paths
+            .into_iter()
+            .map(|path| Ok(Package::from_path(path)?.with_repository(&self.name)))
+            .collect()
+    }
+}
+
+fn enabled() -> bool {
+    true
+}
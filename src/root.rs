@@ -0,0 +1,110 @@
+use {
+    super::{Error, Result},
+    camino::{Utf8Path, Utf8PathBuf},
+    std::path,
+};
+
+/// Mocha's filesystem layout.
+///
+/// ```text
+/// <root>/bin             installed binaries
+/// <root>/lib             installed libraries
+/// <root>/include         installed headers
+/// <root>/share           manual pages, completions and package data
+/// <root>/src/<package>   package sources
+/// <root>/repos/<name>    package repositories
+/// <root>/installed.yaml  installed-state database
+/// <root>/repos.yaml      repository configuration
+/// <root>/log/<package>   build logs
+/// <root>/cache           fetched sources, see `Cache`
+/// <root>/.staging        artifacts waiting to be moved into place
+/// ```
+#[derive(Clone, Debug)]
+pub struct Root {
+    path: Utf8PathBuf,
+}
+
+impl Root {
+    /// Use `path` as the root, relative paths are resolved against the
+    /// current directory.
+    pub fn new<P: AsRef<Utf8Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let absolute = path::absolute(path).map_err(|error| Error::io(path, error))?;
+        let absolute = Utf8PathBuf::try_from(absolute)
+            .map_err(|error| Error::io(path, error.into_io_error()))?;
+
+        Ok(Self { path: absolute })
+    }
+
+    pub fn path(&self) -> &Utf8Path {
+        &self.path
+    }
+
+    pub fn bin_dir(&self) -> Utf8PathBuf {
+        self.path.join("bin")
+    }
+
+    pub fn lib_dir(&self) -> Utf8PathBuf {
+        self.path.join("lib")
+    }
+
+    pub fn include_dir(&self) -> Utf8PathBuf {
+        self.path.join("include")
+    }
+
+    pub fn share_dir(&self) -> Utf8PathBuf {
+        self.path.join("share")
+    }
+
+    pub fn src_dir(&self) -> Utf8PathBuf {
+        self.path.join("src")
+    }
+
+    /// Where source `index` of `package` is checked out, the first one
+    /// at `<root>/src/<package>`, others at `<root>/src/<package>.<index>`.
+    pub fn source_dir(&self, package: &str, index: usize) -> Utf8PathBuf {
+        match index {
+            0 => self.src_dir().join(package),
+            index => self.src_dir().join(format!("{package}.{index}")),
+        }
+    }
+
+    /// Every existing checkout of `package`, see [`Root::source_dir`].
+    pub fn source_dirs(&self, package: &str) -> Vec<Utf8PathBuf> {
+        self.src_dir()
+            .read_dir_utf8()
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| {
+                let name = entry.file_name();
+
+                name == package
+                    || name
+                        .strip_prefix(package)
+                        .and_then(|rest| rest.strip_prefix('.'))
+                        .is_some_and(|index| {
+                            !index.is_empty()
+                                && index.chars().all(|character| character.is_ascii_digit())
+                        })
+            })
+            .map(|entry| entry.into_path())
+            .collect()
+    }
+
+    pub fn repos_dir(&self) -> Utf8PathBuf {
+        self.path.join("repos")
+    }
+
+    pub fn log_dir(&self) -> Utf8PathBuf {
+        self.path.join("log")
+    }
+
+    pub fn cache_dir(&self) -> Utf8PathBuf {
+        self.path.join("cache")
+    }
+
+    pub fn staging_dir(&self) -> Utf8PathBuf {
+        self.path.join(".staging")
+    }
+}
@@ -0,0 +1,163 @@
+use {
+    super::{
+        cache::Cache,
+        database::{Database, Record},
+        graph::{Graph, Node},
+        package::{self, Environment},
+        root::Root,
+        toolchain::Toolchain,
+        Error, Result,
+    },
+    camino::Utf8PathBuf,
+    jobserver::Client,
+    milk_progress::Progress,
+    std::{
+        collections::HashMap,
+        num::NonZeroUsize,
+        sync::{mpsc, Mutex, PoisonError},
+        thread,
+    },
+    tokio::runtime,
+};
+
+/// Install every package of `graph`.
+///
+/// A package is built as soon as its dependencies are installed, so
+/// independent packages build concurrently. All builds share one jobserver
+/// with `jobs` tokens, which is passed on to cargo and zig.
+///
+/// After a failure no new builds are started. Builds already running are
+/// finished and recorded, then the first error is returned.
+pub fn install(
+    graph: &Graph<'_>,
+    root: &Root,
+    toolchain: &Toolchain,
+    jobs: NonZeroUsize,
+    cache: &Cache,
+    database: &mut Database,
+) -> Result<()> {
+    let jobserver = Client::new(jobs.get()).map_err(Error::jobserver)?;
+
+    // Targets of one package share its source, staging and log paths, so
+    // their builds must not overlap.
+    let locks = graph
+        .indices()
+        .map(|index| (graph.node(index).package.name(), Mutex::new(())))
+        .collect::<HashMap<_, _>>();
+
+    let mut pending = graph
+        .indices()
+        .map(|index| (index, graph.dependency_count(index)))
+        .collect::<HashMap<_, _>>();
+
+    let mut ready = graph
+        .indices()
+        .filter(|index| pending[index] == 0)
+        .collect::<Vec<_>>();
+
+    let mut running = 0;
+    let mut failure = None;
+    let (sender, receiver) = mpsc::channel();
+    let progress = Progress::new();
+
+    let environment = Environment {
+        root,
+        toolchain,
+        jobserver: &jobserver,
+        progress: &progress,
+        cache,
+    };
+
+    thread::scope(|scope| loop {
+        if failure.is_none() {
+            for index in ready.drain(..) {
+                let sender = sender.clone();
+                let environment = &environment;
+                let lock = &locks[graph.node(index).package.name()];
+                let previous = database.owned_files(&graph.node(index).atom);
+
+                scope.spawn(move || {
+                    // Nothing is shared through the lock, a poisoned one is fine.
+                    let _guard = lock.lock().unwrap_or_else(PoisonError::into_inner);
+
+                    let result = build(graph.node(index), environment, &previous);
+
+                    let _ = sender.send((index, result));
+                });
+
+                running += 1;
+            }
+        }
+
+        if running == 0 {
+            break;
+        }
+
+        let Ok((index, result)) = receiver.recv() else {
+            break;
+        };
+
+        running -= 1;
+
+        let record = match result {
+            Ok(record) => record,
+            Err(error) => {
+                failure.get_or_insert(error);
+                continue;
+            }
+        };
+
+        database.insert(record);
+
+        if let Err(error) = database.save() {
+            failure.get_or_insert(error);
+            continue;
+        }
+
+        for dependent in graph.dependents(index) {
+            if let Some(count) = pending.get_mut(&dependent) {
+                *count -= 1;
+
+                if *count == 0 {
+                    ready.push(dependent);
+                }
+            }
+        }
+    });
+
+    failure.map_or(Ok(()), Err)
+}
+
+/// Build and install a single package on the current thread.
+fn build(
+    node: &Node<'_>,
+    environment: &Environment<'_>,
+    previous: &[Utf8PathBuf],
+) -> Result<Record> {
+    let Node { package, atom } = node;
+    let Environment {
+        jobserver,
+        progress,
+        ..
+    } = environment;
+    let name = package.name();
+
+    // Cargo and zig only take tokens for jobs beyond their first, the first
+    // one is covered by this token.
+    let _token = jobserver
+        .acquire()
+        .map_err(|error| package::build_error(name, "jobserver", error))?;
+
+    let runtime = runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|error| package::build_error(name, "runtime", error))?;
+
+    progress.println(format_args!(" -> {atom}"));
+
+    let result = runtime.block_on(package.install(environment, atom, previous));
+
+    progress.finish(name, result.is_ok());
+
+    result
+}
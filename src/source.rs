@@ -0,0 +1,312 @@
+use {
+    super::{
+        cache::Cache,
+        git::{self, git},
+        log::Log,
+    },
+    camino::{Utf8Path, Utf8PathBuf},
+    std::{
+        fmt, fs, io,
+        os::unix::fs::symlink,
+        process::{Command, Stdio},
+    },
+};
+
+/// Where the source of a package comes from.
+///
+/// ```text
+/// github:<owner>/<repo>                 https://github.com/<owner>/<repo>.git
+/// gitlab:<owner>/<repo>                 https://gitlab.com/<owner>/<repo>.git
+/// git+<url>                             <url>
+/// https://<url>.tar.gz#sha256=<hex>     a tarball, other compressions work too
+/// path:<dir>                            a directory, relative to the spec, copied
+/// <anything else>                       a git url
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Source {
+    Git {
+        url: String,
+    },
+    /// Verified against `sha256` before it is extracted.
+    Tarball {
+        url: String,
+        sha256: String,
+    },
+    /// Copied before it is built, builds write into their source.
+    Path {
+        path: Utf8PathBuf,
+    },
+}
+
+/// A source tree ready to be built.
+pub struct Checkout {
+    pub dir: Utf8PathBuf,
+    /// The commit, checksum or `local` for directories.
+    pub revision: String,
+}
+
+/// Not copied from the top level of `path:` sources.
+const SKIPPED_DIRS: &[&str] = &[".git", "target", "zig-out", "zig-cache", ".zig-cache"];
+
+const TARBALL_EXTENSIONS: &[&str] = &[
+    ".tar", ".tar.gz", ".tgz", ".tar.xz", ".txz", ".tar.bz2", ".tbz2", ".tar.zst",
+];
+
+impl Source {
+    /// Parse `source`, relative paths are resolved against `spec_dir`.
+    pub fn parse(source: &str, spec_dir: &Utf8Path) -> Result<Self, String> {
+        let Some((scheme, rest)) = split_scheme(source) else {
+            return Ok(Self::Git { url: source.into() });
+        };
+
+        match scheme {
+            "github" => Ok(Self::Git {
+                url: format!("https://github.com/{}.git", repository(rest)?),
+            }),
+            "gitlab" => Ok(Self::Git {
+                url: format!("https://gitlab.com/{}.git", repository(rest)?),
+            }),
+            "path" => Ok(Self::Path {
+                path: spec_dir.join(rest),
+            }),
+            "http" | "https" => {
+                let (url, sha256) = match source.split_once("#sha256=") {
+                    Some((url, sha256)) => (url, Some(sha256)),
+                    None => (source, None),
+                };
+
+                let is_tarball = TARBALL_EXTENSIONS
+                    .iter()
+                    .any(|extension| url.ends_with(extension));
+
+                match sha256 {
+                    Some(sha256) if is_sha256(sha256) => Ok(Self::Tarball {
+                        url: url.into(),
+                        sha256: sha256.to_ascii_lowercase(),
+                    }),
+                    Some(_) => Err(String::from("`sha256` must be 64 hexadecimal digits")),
+                    None if is_tarball => {
+                        Err(String::from("tarballs need a `#sha256=<hex>` checksum"))
+                    }
+                    None => Ok(Self::Git { url: source.into() }),
+                }
+            }
+            "file" | "ssh" | "git" => Ok(Self::Git { url: source.into() }),
+            scheme => match scheme.strip_prefix("git+") {
+                Some(_) => Ok(Self::Git {
+                    url: source["git+".len()..].into(),
+                }),
+                None => Err(format!("unknown source scheme `{scheme}`")),
+            },
+        }
+    }
+
+    /// Check the source out into `dir` from `cache`, unless it is a
+    /// directory already.
+    ///
+    /// `reference` only applies to git sources, it is an error for others.
+    pub fn fetch(
+        &self,
+        dir: &Utf8Path,
+        reference: Option<&str>,
+        cache: &Cache,
+        log: &mut Log,
+    ) -> io::Result<Checkout> {
+        if reference.is_some() && !matches!(self, Self::Git { .. }) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "`ref` only applies to git sources",
+            ));
+        }
+
+        match self {
+            Self::Git { url } => {
+                // Held until checked out, an update of the mirror could prune the reference.
+                let mirror = cache.git(url, log)?;
+
+                // Only full commit hashes can be fetched, not abbreviated ones.
+                let reference = reference
+                    .map(|reference| resolve(mirror.path(), reference))
+                    .transpose()?;
+
+                checkout(
+                    dir,
+                    &format!("file://{}", mirror.path()),
+                    reference.as_deref(),
+                    log,
+                )?;
+
+                Ok(Checkout {
+                    dir: dir.into(),
+                    revision: revision(dir)?,
+                })
+            }
+            Self::Tarball { url, sha256 } => {
+                let tarball = cache.tarball(url, sha256, log)?;
+                let tree = extract(dir, tarball.path(), log)?;
+
+                Ok(Checkout {
+                    dir: tree,
+                    revision: format!("sha256:{sha256}"),
+                })
+            }
+            Self::Path { path } => {
+                if !path.is_dir() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("{path} is not a directory"),
+                    ));
+                }
+
+                remove_dir_all(dir)?;
+                copy_tree(path, dir, SKIPPED_DIRS)?;
+
+                Ok(Checkout {
+                    dir: dir.into(),
+                    revision: String::from("local"),
+                })
+            }
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Git { url } => fmt.write_str(url),
+            Self::Tarball { url, sha256 } => write!(fmt, "{url}#sha256={sha256}"),
+            Self::Path { path } => write!(fmt, "path:{path}"),
+        }
+    }
+}
+
+/// Split `<scheme>:<rest>`, scp-like git urls such as `git@host:path` have no scheme.
+fn split_scheme(source: &str) -> Option<(&str, &str)> {
+    let (scheme, rest) = source.split_once(':')?;
+
+    let is_scheme = scheme.starts_with(|character: char| character.is_ascii_alphabetic())
+        && scheme.chars().all(|character| {
+            character.is_ascii_alphanumeric() || matches!(character, '+' | '-' | '.')
+        });
+
+    is_scheme.then_some((scheme, rest))
+}
+
+/// Validate `<owner>/<repo>` of forge shorthands.
+fn repository(repository: &str) -> Result<&str, String> {
+    let repository = repository.strip_suffix(".git").unwrap_or(repository);
+
+    match repository.split_once('/') {
+        Some((owner, name)) if !owner.is_empty() && !name.is_empty() => Ok(repository),
+        _ => Err(format!("expected `<owner>/<repo>`, found `{repository}`")),
+    }
+}
+
+fn is_sha256(sha256: &str) -> bool {
+    sha256.len() == 64
+        && sha256
+            .chars()
+            .all(|character| character.is_ascii_hexdigit())
+}
+
+/// Extract `tarball` into `dir`.
+///
+/// Returns the source tree, the single top-level directory most tarballs
+/// have, or `dir` itself.
+fn extract(dir: &Utf8Path, tarball: &Utf8Path, log: &mut Log) -> io::Result<Utf8PathBuf> {
+    remove_dir_all(dir)?;
+    fs::create_dir_all(dir)?;
+
+    log.run(
+        Command::new("tar")
+            .arg("--extract")
+            .arg("--file")
+            .arg(tarball)
+            .arg("--directory")
+            .arg(dir)
+            .stdin(Stdio::null()),
+    )?;
+
+    let entries = dir.read_dir_utf8()?.collect::<io::Result<Vec<_>>>()?;
+
+    match entries.as_slice() {
+        [entry] if entry.file_type()?.is_dir() => Ok(entry.path().into()),
+        _ => Ok(dir.into()),
+    }
+}
+
+/// Copy the directory `from` to `to`, symlinks are copied as they are.
+///
+/// Directories of `from` named in `skipped` are left out, but not those
+/// further down, a nested `target` may well be a module.
+fn copy_tree(from: &Utf8Path, to: &Utf8Path, skipped: &[&str]) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+
+    for entry in from.read_dir_utf8()? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let destination = to.join(entry.file_name());
+
+        if file_type.is_dir() {
+            if !skipped.contains(&entry.file_name()) {
+                copy_tree(entry.path(), &destination, &[])?;
+            }
+        } else if file_type.is_symlink() {
+            symlink(entry.path().read_link_utf8()?, &destination)?;
+        } else {
+            fs::copy(entry.path(), &destination)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Remove `dir` and everything in it, if it exists.
+pub fn remove_dir_all(dir: &Utf8Path) -> io::Result<()> {
+    match fs::remove_dir_all(dir) {
+        Ok(()) => Ok(()),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+/// Fetch `reference` of `url` into `source_dir` and check it out.
+///
+/// Only that commit is fetched. Without a reference, the default branch is used.
+fn checkout(
+    source_dir: &Utf8Path,
+    url: &str,
+    reference: Option<&str>,
+    log: &mut Log,
+) -> io::Result<()> {
+    // A failed clone leaves an empty directory behind.
+    if source_dir.join(".git").exists() {
+        log.run(git(source_dir).args(["remote", "set-url", "origin", url]))?;
+    } else {
+        fs::create_dir_all(source_dir)?;
+        log.run(git(source_dir).args(["init", "--quiet"]))?;
+        log.run(git(source_dir).args(["remote", "add", "origin", url]))?;
+    }
+
+    let reference = reference.unwrap_or("HEAD");
+
+    log.run(git(source_dir).args(["fetch", "--quiet", "--depth", "1", "origin", reference]))?;
+    log.run(git(source_dir).args(["checkout", "--quiet", "--force", "--detach", "FETCH_HEAD"]))
+}
+
+/// Resolve `reference`, a tag, branch or possibly abbreviated commit, to
+/// a commit of `mirror`.
+fn resolve(mirror: &Utf8Path, reference: &str) -> io::Result<String> {
+    git::rev_parse(mirror, reference)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no tag, branch or commit `{reference}`"),
+        )
+    })
+}
+
+/// Resolve the commit checked out in `source_dir`.
+fn revision(source_dir: &Utf8Path) -> io::Result<String> {
+    git::rev_parse(source_dir, "HEAD")?
+        .ok_or_else(|| io::Error::other(format!("nothing is checked out in {source_dir}")))
+}
@@ -0,0 +1,129 @@
+use {
+    super::{git, package::Package, repository::Repository, root::Root},
+    camino::{Utf8Path, Utf8PathBuf},
+    std::{collections::BTreeMap, fs, io},
+};
+
+/// Retrieves repositories.
+pub trait Fetcher {
+    /// Create a new copy of the repository at `url` in `repository`.
+    fn checkout(&self, url: &str, repository: &Utf8Path) -> io::Result<()>;
+
+    /// Update a repository in place.
+    fn fetch(&self, repository: &Utf8Path) -> io::Result<()>;
+}
+
+/// Fetch with `git fetch`, then fast-forward to the upstream branch.
+pub struct Git;
+
+/// Specs that differ between two syncs of a repository.
+#[derive(Debug, Default)]
+pub struct Changes {
+    pub added: Vec<Utf8PathBuf>,
+    pub changed: Vec<Utf8PathBuf>,
+    pub removed: Vec<Utf8PathBuf>,
+}
+
+impl Fetcher for Git {
+    fn checkout(&self, url: &str, repository: &Utf8Path) -> io::Result<()> {
+        let parent = repository.parent().unwrap_or(Utf8Path::new("."));
+
+        fs::create_dir_all(parent)?;
+        git::run(parent, &["clone", "--quiet", url, repository.as_str()])
+    }
+
+    fn fetch(&self, repository: &Utf8Path) -> io::Result<()> {
+        git::run(repository, &["fetch", "--quiet"])?;
+        git::run(repository, &["merge", "--quiet", "--ff-only", "FETCH_HEAD"])?;
+
+        Ok(())
+    }
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+
+    /// Specs that have to be validated again.
+    pub fn modified(&self) -> impl Iterator<Item = &Utf8Path> {
+        self.added
+            .iter()
+            .chain(self.changed.iter())
+            .map(|path| path.as_path())
+    }
+}
+
+/// Update `repository` with `fetcher` and report which specs changed.
+///
+/// Missing repositories are checked out from their URL.
+pub fn sync(fetcher: &dyn Fetcher, root: &Root, repository: &Repository) -> io::Result<Changes> {
+    let path = repository.path(root);
+
+    let before = if path.exists() {
+        let before = snapshot(&path)?;
+
+        fetcher.fetch(&path)?;
+        before
+    } else if let Some(url) = &repository.url {
+        fetcher.checkout(url, &path)?;
+        BTreeMap::new()
+    } else {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{path} does not exist and has no url to clone from"),
+        ));
+    };
+
+    let after = snapshot(&path)?;
+    let mut changes = Changes::default();
+
+    for (path, content) in &after {
+        match before.get(path) {
+            Some(previous) if previous == content => {}
+            Some(_) => changes.changed.push(path.clone()),
+            None => changes.added.push(path.clone()),
+        }
+    }
+
+    for path in before.keys() {
+        if !after.contains_key(path) {
+            changes.removed.push(path.clone());
+        }
+    }
+
+    Ok(changes)
+}
+
+/// Validate the specs that were added or changed.
+///
+/// Returns whether all of them are valid.
+pub fn validate(changes: &Changes) -> bool {
+    let mut valid = true;
+
+    for path in changes.modified() {
+        if let Err(error) = Package::from_path(path) {
+            error.report();
+            valid = false;
+        }
+    }
+
+    valid
+}
+
+/// Read the content of every spec in `repository`.
+fn snapshot(repository: &Utf8Path) -> io::Result<BTreeMap<Utf8PathBuf, Vec<u8>>> {
+    let mut specs = BTreeMap::new();
+
+    for entry in repository.read_dir_utf8()? {
+        let entry = entry?;
+
+        if entry.file_type()?.is_file() {
+            let content = fs::read(entry.path())?;
+
+            specs.insert(entry.into_path(), content);
+        }
+    }
+
+    Ok(specs)
+}
@@ -0,0 +1,212 @@
+use {
+    super::{Error, Result},
+    camino::{Utf8Path, Utf8PathBuf},
+    std::{
+        env, fmt,
+        os::unix::fs::PermissionsExt,
+        process::{Command, Stdio},
+    },
+};
+
+/// Oldest cargo known to work.
+const MIN_CARGO: Version = Version(1, 70, 0);
+
+/// Oldest zig known to work, `build.zig` generation relies on the 0.11 build API.
+const MIN_ZIG: Version = Version(0, 11, 0);
+
+/// First zig without the generated API, 0.13 removed `LazyPath.path`.
+const MAX_GENERATED_ZIG: Version = Version(0, 13, 0);
+
+/// Where cargo is looked for unless configured.
+pub const DEFAULT_CARGO: &str = "~/.cargo/bin/cargo";
+
+/// Where zig is looked for unless configured.
+pub const DEFAULT_ZIG: &str = "~/.zig/zig";
+
+/// Build tools used to install packages.
+#[derive(Debug)]
+pub struct Toolchain {
+    pub cargo: Utf8PathBuf,
+    /// Only resolved if a package uses it.
+    pub zig: Option<Utf8PathBuf>,
+}
+
+/// How much of zig the packages to install rely on, ordered by strictness.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Zig {
+    Unused,
+    /// `cargo zigbuild` or the `build.zig` of a source.
+    Any,
+    /// A generated `build.zig`, which uses the 0.11 build API.
+    Generated,
+}
+
+#[derive(Clone, Copy, Eq, Ord, PartialEq, PartialOrd)]
+struct Version(u32, u32, u32);
+
+impl Toolchain {
+    /// Resolve and validate the cargo binary, and the zig binary unless
+    /// it is `Unused`.
+    pub fn resolve(cargo_path: &str, zig_path: &str, zig: Zig) -> Result<Self> {
+        let cargo = Self::cargo(cargo_path)?;
+
+        let maximum = match zig {
+            Zig::Unused => return Ok(Self { cargo, zig: None }),
+            Zig::Any => None,
+            Zig::Generated => Some(MAX_GENERATED_ZIG),
+        };
+
+        let zig = resolve("zig", zig_path, DEFAULT_ZIG)?;
+
+        check_version("zig", &zig, "version", MIN_ZIG, maximum)?;
+
+        Ok(Self {
+            cargo,
+            zig: Some(zig),
+        })
+    }
+
+    /// The zig binary, for parts which were accounted for when resolving.
+    pub fn zig(&self) -> Result<&Utf8Path> {
+        self.zig
+            .as_deref()
+            .ok_or_else(|| Error::toolchain("zig", "zig is used but was not resolved"))
+    }
+
+    /// Resolve and validate just the cargo binary, for fetching.
+    pub fn cargo(cargo_path: &str) -> Result<Utf8PathBuf> {
+        let cargo = resolve("cargo", cargo_path, DEFAULT_CARGO)?;
+
+        check_version("cargo", &cargo, "--version", MIN_CARGO, None)?;
+
+        Ok(cargo)
+    }
+}
+
+/// Expand `~` and locate `path`.
+///
+/// Bare names are searched for in `PATH`. If `path` is the `default` and
+/// does not exist, `PATH` is searched for its file name instead, so the
+/// defaults work for toolchains installed elsewhere.
+fn resolve(tool: &str, path: &str, default: &str) -> Result<Utf8PathBuf> {
+    let expanded = expand_home(path);
+
+    if expanded.as_str().contains('/') {
+        if is_executable(&expanded) {
+            return Ok(expanded);
+        }
+
+        if path != default {
+            return Err(Error::toolchain(
+                tool,
+                &format!("`{path}` is not an executable"),
+            ));
+        }
+    }
+
+    let name = expanded.file_name().unwrap_or(tool);
+
+    search_path(name).ok_or_else(|| {
+        Error::toolchain(
+            tool,
+            &format!("`{path}` not found and `{name}` is not in PATH"),
+        )
+    })
+}
+
+fn expand_home(path: &str) -> Utf8PathBuf {
+    let home = env::var("HOME").ok();
+
+    match (path.strip_prefix('~'), home) {
+        (Some(""), Some(home)) => Utf8PathBuf::from(home),
+        (Some(rest), Some(home)) if rest.starts_with('/') => {
+            Utf8PathBuf::from(format!("{home}{rest}"))
+        }
+        _ => Utf8PathBuf::from(path),
+    }
+}
+
+fn search_path(name: &str) -> Option<Utf8PathBuf> {
+    let path = env::var("PATH").ok()?;
+
+    path.split(':')
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| Utf8Path::new(dir).join(name))
+        .find(|path| is_executable(path))
+}
+
+fn is_executable(path: &Utf8Path) -> bool {
+    path.metadata().is_ok_and(|metadata| {
+        metadata.file_type().is_file() && metadata.permissions().mode() & 0o111 != 0
+    })
+}
+
+/// Check that the version of `path` is at least `minimum`, and below
+/// `maximum` for generated `build.zig` files.
+fn check_version(
+    tool: &str,
+    path: &Utf8Path,
+    arg: &str,
+    minimum: Version,
+    maximum: Option<Version>,
+) -> Result<()> {
+    let output = Command::new(path)
+        .arg(arg)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|error| Error::toolchain(tool, &format!("failed to run `{path}`: {error}")))?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let version = output
+        .status
+        .success()
+        .then(|| Version::parse(&stdout))
+        .flatten()
+        .ok_or_else(|| {
+            Error::toolchain(
+                tool,
+                &format!("unable to determine the version of `{path}`"),
+            )
+        })?;
+
+    if version < minimum {
+        return Err(Error::toolchain(
+            tool,
+            &format!("`{path}` is version {version}, at least {minimum} is required"),
+        ));
+    }
+
+    if let Some(maximum) = maximum.filter(|maximum| version >= *maximum) {
+        return Err(Error::toolchain(
+            tool,
+            &format!("`{path}` is version {version}, generated `build.zig` files need a version before {maximum}"),
+        ));
+    }
+
+    Ok(())
+}
+
+impl Version {
+    /// Parse the first `x.y.z` in `output`, e.g. `cargo 1.70.0 (ec8a8a0ca 2023-04-25)`
+    /// or `0.11.0-dev.3299+34865d693`.
+    fn parse(output: &str) -> Option<Self> {
+        output.split_whitespace().find_map(|word| {
+            let word = word.split(['-', '+']).next()?;
+            let mut parts = word.splitn(3, '.').map(str::parse);
+
+            match (parts.next()?, parts.next()?, parts.next()?) {
+                (Ok(major), Ok(minor), Ok(patch)) => Some(Self(major, minor, patch)),
+                _ => None,
+            }
+        })
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self(major, minor, patch) = self;
+
+        write!(fmt, "{major}.{minor}.{patch}")
+    }
+}
@@ -0,0 +1,171 @@
+use {
+    super::{Error, Result},
+    camino::{Utf8Path, Utf8PathBuf},
+    std::{fs, io, os::unix},
+};
+
+/// Installs a set of files all at once.
+///
+/// Files are staged first, then renamed into place. If any file fails to be
+/// put in place, the files already replaced or removed are restored, so a
+/// failed install never leaves a half-updated system.
+///
+/// The staging directory must be on the same filesystem as the destinations.
+pub struct Transaction {
+    staging_dir: Utf8PathBuf,
+    entries: Vec<Entry>,
+}
+
+struct Entry {
+    /// Nothing to remove the destination.
+    staged: Option<Utf8PathBuf>,
+    destination: Utf8PathBuf,
+}
+
+/// An entry that has been renamed into place.
+struct Applied<'a> {
+    destination: &'a Utf8Path,
+    backup: Option<Utf8PathBuf>,
+}
+
+impl Transaction {
+    /// Begin a transaction staged in `staging_dir`.
+    ///
+    /// Leftovers of an interrupted transaction are discarded.
+    pub fn new<P: Into<Utf8PathBuf>>(staging_dir: P) -> Result<Self> {
+        let staging_dir = staging_dir.into();
+
+        match fs::remove_dir_all(&staging_dir) {
+            Ok(()) => {}
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(Error::io(&staging_dir, error)),
+        }
+
+        fs::create_dir_all(&staging_dir).map_err(|error| Error::io(&staging_dir, error))?;
+
+        Ok(Self {
+            staging_dir,
+            entries: Vec::new(),
+        })
+    }
+
+    /// Stage a copy of `source` to be installed at `destination`.
+    pub fn copy(&mut self, source: &Utf8Path, destination: Utf8PathBuf) -> Result<()> {
+        let staged = self.next_staged();
+
+        fs::copy(source, &staged).map_err(|error| Error::io(source, error))?;
+
+        self.entries.push(Entry {
+            staged: Some(staged),
+            destination,
+        });
+
+        Ok(())
+    }
+
+    /// Stage a symlink to `points_to` to be installed at `destination`.
+    pub fn symlink(&mut self, points_to: &str, destination: Utf8PathBuf) -> Result<()> {
+        let staged = self.next_staged();
+
+        unix::fs::symlink(points_to, &staged).map_err(|error| Error::io(&staged, error))?;
+
+        self.entries.push(Entry {
+            staged: Some(staged),
+            destination,
+        });
+
+        Ok(())
+    }
+
+    /// Remove `destination`, if it exists.
+    pub fn remove(&mut self, destination: Utf8PathBuf) {
+        self.entries.push(Entry {
+            staged: None,
+            destination,
+        });
+    }
+
+    /// Move every staged file into place.
+    pub fn commit(self) -> Result<()> {
+        let mut applied = Vec::with_capacity(self.entries.len());
+
+        for (index, entry) in self.entries.iter().enumerate() {
+            match self.apply(index, entry) {
+                Ok(entry) => applied.push(entry),
+                Err(error) => {
+                    rollback(applied);
+
+                    return Err(error);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replace or remove the destination of `entry`, keeping the previous
+    /// file as a backup.
+    fn apply<'a>(&self, index: usize, entry: &'a Entry) -> Result<Applied<'a>> {
+        let Entry {
+            staged,
+            destination,
+        } = entry;
+
+        let backup = match destination.symlink_metadata() {
+            Ok(_) => {
+                let backup = self.staging_dir.join(format!("{index}.backup"));
+
+                fs::hard_link(destination, &backup)
+                    .map_err(|error| Error::io(destination, error))?;
+
+                Some(backup)
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+            Err(error) => return Err(Error::io(destination, error)),
+        };
+
+        let result = match staged {
+            // Atomically replaces an existing destination.
+            Some(staged) => fs::rename(staged, destination),
+            None if backup.is_some() => fs::remove_file(destination),
+            None => Ok(()),
+        };
+
+        if let Err(error) = result {
+            if let Some(backup) = backup {
+                let _ = fs::remove_file(backup);
+            }
+
+            return Err(Error::io(destination, error));
+        }
+
+        Ok(Applied {
+            destination,
+            backup,
+        })
+    }
+
+    fn next_staged(&self) -> Utf8PathBuf {
+        self.staging_dir.join(self.entries.len().to_string())
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.staging_dir);
+    }
+}
+
+/// Restore the previous state of the applied entries, most recent first.
+fn rollback(applied: Vec<Applied<'_>>) {
+    for Applied {
+        destination,
+        backup,
+    } in applied.into_iter().rev()
+    {
+        let _ = match backup {
+            Some(backup) => fs::rename(backup, destination),
+            None => fs::remove_file(destination),
+        };
+    }
+}
@@ -1,80 +1,257 @@
 use {
-    super::{artifact::Artifact, atom::Atom, error::Error, package, Result},
-    camino::Utf8PathBuf,
-    clap::{arg, Args, Parser},
+    super::{
+        artifact::Artifact,
+        atom::Atom,
+        cache::Cache,
+        database::Database,
+        error::Error,
+        graph::{Graph, Node},
+        log::Log,
+        package,
+        repository::Config,
+        root::Root,
+        scheduler, sync,
+        toolchain::{self, Toolchain, Zig},
+        Result,
+    },
+    camino::{Utf8Path, Utf8PathBuf},
+    clap::{arg, Args, Parser, Subcommand},
+    std::{fs, io, num::NonZeroUsize, process, thread},
 };
 
 /// Mocha's package manager.
 #[derive(Debug, Parser)]
-pub enum Milk {
+pub struct Milk {
+    /// Root of the Mocha filesystem layout.
+    #[arg(long, env = "MILK_ROOT", default_value = "/mocha", global = true)]
+    root: Utf8PathBuf,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
     /// Install packages.
     Add(AddArgs),
 
+    /// Fetch sources into the cache, for offline installs.
+    Fetch(FetchArgs),
+
     /// Format package specifications.
     Fmt(FmtArgs),
 
+    /// List installed packages.
+    List,
+
+    /// Show the latest build log of a package.
+    Log(LogArgs),
+
+    /// Uninstall packages.
+    Remove(RemoveArgs),
+
     /// Sync repositories.
     Sync,
 }
 
 impl Milk {
     pub async fn run() {
-        match Self::parse() {
-            Milk::Add(AddArgs { atoms, flags }) => {
-                let packages = walkdir::WalkDir::new("/mocha/repos")
-                    .max_depth(2)
-                    .min_depth(2)
-                    .sort_by_file_name()
-                    .into_iter()
-                    .flatten()
-                    .flat_map(|entry| {
-                        if entry.file_type().is_file() {
-                            let path = camino::Utf8Path::from_path(entry.path())?;
-                            let _repository = path.parent()?.as_str();
-                            let _spec = path.file_name()?;
-
-                            match package::Package::from_path(path) {
-                                Ok(package) => Some(package),
-                                Err(error) => error.emit(),
-                            }
-                        } else {
-                            None
-                        }
-                    })
-                    .collect::<Vec<_>>();
+        let Self { root, command } = Self::parse();
+        let result = match Root::new(root) {
+            Ok(root) => command.run(&root).await,
+            Err(error) => Err(error),
+        };
+
+        if let Err(error) = result {
+            error.emit();
+        }
+    }
+}
+
+impl Command {
+    async fn run(self, root: &Root) -> Result<()> {
+        match self {
+            Command::Add(AddArgs {
+                atoms,
+                flags:
+                    AddFlags {
+                        cargo_path,
+                        zig_path,
+                        jobs,
+                        offline,
+                    },
+            }) => {
+                let packages = Config::load(root)?.packages(root)?;
 
                 if atoms.is_empty() {
+                    let database = Database::open(root)?;
+
                     for package in packages {
-                        println!("{}", package.name());
-                        println!("  {}", package.source());
+                        let installed = database
+                            .get_package(package.name())
+                            .map(|record| record.atom.target.to_string())
+                            .collect::<Vec<_>>();
+
+                        let name = format!("{}/{}", package.repository(), package.name());
+
+                        if installed.is_empty() {
+                            println!("{name}");
+                        } else {
+                            println!("{name} (installed: {})", installed.join(", "));
+                        }
+
+                        for source in package.sources() {
+                            println!("  {source}");
+                        }
                         println!("  {:?}", package.features());
                         println!("  {:?}", package.artifacts());
                         println!("  {:?}", package.dependencies());
                         println!();
                     }
                 } else {
-                    for atom in atoms {
-                        println!(" -> {atom}");
+                    let graph = Graph::resolve(&packages, &atoms)?;
+                    let zig = graph
+                        .indices()
+                        .map(|index| graph.node(index).package.zig())
+                        .max()
+                        .unwrap_or(Zig::Unused);
+
+                    let toolchain = Toolchain::resolve(&cargo_path, &zig_path, zig)?;
 
-                        let package = packages
-                            .iter()
-                            .find(|package| package.name() == atom.package);
+                    let jobs = jobs.unwrap_or_else(|| {
+                        thread::available_parallelism().unwrap_or(NonZeroUsize::MIN)
+                    });
+
+                    let cache = Cache::new(root, offline);
+                    let mut database = Database::open(root)?;
+
+                    scheduler::install(&graph, root, &toolchain, jobs, &cache, &mut database)?;
+                }
+            }
+            Command::Fetch(FetchArgs { atoms, cargo_path }) => {
+                let packages = Config::load(root)?.packages(root)?;
+                let graph = Graph::resolve(&packages, &atoms)?;
+                let cargo = Toolchain::cargo(&cargo_path)?;
+                let cache = Cache::new(root, false);
+
+                for index in graph.indices() {
+                    let Node { package, atom } = graph.node(index);
+
+                    println!(" -> {atom}");
+
+                    package.fetch(root, &cache, &cargo, atom)?;
+                }
+            }
+            Command::Fmt(FmtArgs { specs }) => {
+                for spec in specs {
+                    package::Package::format(spec)?;
+                }
+            }
+            Command::List => {
+                let database = Database::open(root)?;
+
+                for record in database.iter() {
+                    println!("{}", record.atom);
+                    println!("  {} {}", record.source, record.revision);
+
+                    for installed in &record.artifacts {
+                        println!("  {:?} {:?}", installed.artifact, installed.files);
+                    }
+
+                    println!();
+                }
+            }
+            Command::Log(LogArgs { package }) => {
+                let path = Log::latest(root, &package)?;
+                let mut file = fs::File::open(&path).map_err(|error| Error::io(&path, error))?;
+
+                // A closed pipe, as with `milk log foo | head`, is not an error.
+                let _ = io::copy(&mut file, &mut io::stdout());
+            }
+            Command::Remove(RemoveArgs { atoms, force }) => {
+                let mut database = Database::open(root)?;
+
+                for atom in &atoms {
+                    if database.get(atom).is_none() {
+                        return Err(Error::not_installed(atom));
+                    }
 
-                        if let Some(package) = package {
-                            package.install(atom.target).await.expect("lol");
+                    let dependents = database
+                        .dependents(atom)
+                        .filter(|record| !atoms.iter().any(|atom| record.matches(atom)))
+                        .map(|record| record.atom.to_string().into_boxed_str())
+                        .collect::<Vec<_>>();
+
+                    if !force && !dependents.is_empty() {
+                        return Err(Error::required_by(atom, dependents));
+                    }
+                }
+
+                for atom in &atoms {
+                    println!(" -> {atom}");
+
+                    if let Some(record) = database.remove(atom) {
+                        record.uninstall()?;
+                    }
+
+                    // Sources are shared between targets.
+                    if database.get_package(&atom.package).next().is_none() {
+                        for source_dir in root.source_dirs(&atom.package) {
+                            fs::remove_dir_all(&source_dir)
+                                .map_err(|error| Error::io(&source_dir, error))?;
                         }
                     }
+
+                    database.save()?;
                 }
             }
-            Milk::Fmt(FmtArgs { specs }) => {
-                for spec in specs {
-                    if let Err(error) = package::Package::format(spec) {
-                        error.emit();
+            Command::Sync => {
+                let config = Config::load(root)?;
+                let mut valid = true;
+
+                for repository in config.repositories() {
+                    println!(" -> {}", repository.name);
+
+                    let changes = match sync::sync(&sync::Git, root, repository) {
+                        Ok(changes) => changes,
+                        Err(error) => {
+                            let source = match &repository.url {
+                                Some(url) => url.clone(),
+                                None => repository.path(root).into_string(),
+                            };
+
+                            Error::fetch(&repository.name, &source, error).report();
+                            valid = false;
+                            continue;
+                        }
+                    };
+
+                    if changes.is_empty() {
+                        println!(" up to date");
                     }
+
+                    for path in &changes.added {
+                        change_log("add", path);
+                    }
+
+                    for path in &changes.changed {
+                        change_log("mod", path);
+                    }
+
+                    for path in &changes.removed {
+                        change_log("del", path);
+                    }
+
+                    valid &= sync::validate(&changes);
+                }
+
+                if !valid {
+                    process::exit(1);
                 }
             }
-            Milk::Sync => println!("sunch"),
         }
+
+        Ok(())
     }
 }
 
@@ -90,10 +267,57 @@ pub struct AddArgs {
 // Build flags
 #[derive(Debug, Clone, Args)]
 pub struct AddFlags {
-    #[arg(long, default_value = "~/.cargo/bin/cargo", help = "Cargo binary")]
+    #[arg(
+        long,
+        env = "MILK_CARGO",
+        default_value = toolchain::DEFAULT_CARGO,
+        help = "Cargo binary"
+    )]
     cargo_path: String,
-    #[arg(long, default_value = "~/.zig/zig", help = "Zig binary")]
+    #[arg(
+        long,
+        env = "MILK_ZIG",
+        default_value = toolchain::DEFAULT_ZIG,
+        help = "Zig binary"
+    )]
     zig_path: String,
+    /// Maximum number of concurrent jobs, defaults to the number of CPUs.
+    #[arg(short, long, env = "MILK_JOBS")]
+    jobs: Option<NonZeroUsize>,
+    /// Install only from the source cache, see `milk fetch`.
+    #[arg(long, env = "MILK_OFFLINE")]
+    offline: bool,
+}
+
+/// Fetch sources into the cache, for offline installs.
+#[derive(Debug, Parser)]
+pub struct FetchArgs {
+    // <package>@<target>
+    #[arg(required = true)]
+    atoms: Vec<Atom>,
+    #[arg(
+        long,
+        env = "MILK_CARGO",
+        default_value = toolchain::DEFAULT_CARGO,
+        help = "Cargo binary"
+    )]
+    cargo_path: String,
+}
+
+/// Show the latest build log of a package.
+#[derive(Debug, Parser)]
+pub struct LogArgs {
+    package: String,
+}
+
+/// Uninstall packages.
+#[derive(Debug, Parser)]
+pub struct RemoveArgs {
+    // <package>@<target>
+    atoms: Vec<Atom>,
+    /// Remove packages even if other packages depend on them.
+    #[arg(long)]
+    force: bool,
 }
 
 /// Format package specifications.
@@ -102,3 +326,19 @@ pub struct FmtArgs {
     /// <package>.spec.
     specs: Vec<Utf8PathBuf>,
 }
+
+fn change_log(kind: &'static str, path: &Utf8Path) {
+    use yansi::{Color, Style};
+
+    let color = match kind {
+        "add" => Color::Green,
+        "del" => Color::Red,
+        _ => Color::Yellow,
+    };
+
+    let kind = Style::new(Color::Black)
+        .bg(color)
+        .paint(format!(" {kind} "));
+
+    println!(" {kind} {}", path.file_name().unwrap_or_default());
+}
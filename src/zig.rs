@@ -0,0 +1,432 @@
+use {
+    camino::{Utf8Path, Utf8PathBuf},
+    serde::{Deserialize, Serialize},
+    std::{collections::HashMap, fmt::Write, io},
+};
+
+/// A C or C++ library or executable, built with `zig build`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Component {
+    pub name: String,
+    pub kind: Kind,
+    /// Source files relative to the package source, glob patterns are expanded.
+    pub sources: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include_dirs: Vec<String>,
+    /// Preprocessor definitions, `NAME` or `NAME=VALUE`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub defines: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cflags: Vec<String>,
+    /// Libraries to link, libraries of this package are preferred over
+    /// system libraries of the same name.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<String>,
+    #[serde(default, skip_serializing_if = "Libc::is_default")]
+    pub libc: Libc,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Static,
+    Shared,
+    Executable,
+}
+
+/// Standard library to link.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Libc {
+    #[serde(rename = "none")]
+    None,
+    #[default]
+    #[serde(rename = "c")]
+    C,
+    #[serde(rename = "c++")]
+    Cxx,
+}
+
+impl Component {
+    fn is_library(&self) -> bool {
+        matches!(self.kind, Kind::Static | Kind::Shared)
+    }
+}
+
+impl Libc {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Convert the legacy `beta_artifacts` spec field.
+///
+/// Entries are named `lib <name>` or `bin <name>`. Executables link every
+/// library of the package, and both use the `lib` and `lib/common` include
+/// directories, as that is what the legacy generator did.
+pub fn from_beta_artifacts(beta_artifacts: &[(String, Vec<String>)]) -> Vec<Component> {
+    let libraries = beta_artifacts
+        .iter()
+        .filter_map(|(artifact, _)| artifact.strip_prefix("lib "))
+        .map(String::from)
+        .collect::<Vec<_>>();
+
+    beta_artifacts
+        .iter()
+        .filter_map(|(artifact, sources)| {
+            let (kind, name, links) = if let Some(name) = artifact.strip_prefix("lib ") {
+                (Kind::Static, name, Vec::new())
+            } else {
+                let name = artifact.strip_prefix("bin ")?;
+
+                (Kind::Executable, name, libraries.clone())
+            };
+
+            Some(Component {
+                name: name.into(),
+                kind,
+                sources: sources.clone(),
+                include_dirs: vec![String::from("lib"), String::from("lib/common")],
+                defines: Vec::new(),
+                cflags: Vec::new(),
+                links,
+                libc: Libc::C,
+            })
+        })
+        .collect()
+}
+
+/// Expand the source globs of `components` against `source_dir`.
+///
+/// A pattern matching no files is an error, it is most likely a typo.
+pub fn expand(components: &[Component], source_dir: &Utf8Path) -> io::Result<Vec<Component>> {
+    components
+        .iter()
+        .map(|component| {
+            let mut sources = Vec::new();
+
+            for pattern in &component.sources {
+                let matches = glob(source_dir, pattern)?;
+
+                if matches.is_empty() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("`{pattern}` of `{}` matches no files", component.name),
+                    ));
+                }
+
+                sources.extend(matches);
+            }
+
+            Ok(Component {
+                sources,
+                ..component.clone()
+            })
+        })
+        .collect()
+}
+
+fn glob(source_dir: &Utf8Path, pattern: &str) -> io::Result<Vec<String>> {
+    let escaped = glob::Pattern::escape(source_dir.as_str());
+    let paths = glob::glob(&format!("{escaped}/{pattern}"))
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+
+    let mut sources = Vec::new();
+
+    for path in paths {
+        let path = path.map_err(io::Error::from)?;
+        let path = Utf8PathBuf::try_from(path).map_err(|error| error.into_io_error())?;
+
+        if let Ok(relative) = path.strip_prefix(source_dir) {
+            sources.push(relative.to_string());
+        }
+    }
+
+    Ok(sources)
+}
+
+/// Generate a `build.zig` for `components`.
+///
+/// Sources are used as given, see [`expand`]. Target and optimization mode
+/// are left to the `-Dtarget` and `-Doptimize` options.
+pub fn render(components: &[Component]) -> String {
+    let mut build = String::new();
+
+    // Formatting into a `String` does not fail.
+    let _ = write_build(&mut build, components);
+
+    build
+}
+
+fn write_build(build: &mut String, components: &[Component]) -> std::fmt::Result {
+    // Libraries of the package, by name.
+    let libraries = components
+        .iter()
+        .enumerate()
+        .filter(|(_, component)| component.is_library())
+        .map(|(index, component)| (component.name.as_str(), index))
+        .collect::<HashMap<_, _>>();
+
+    writeln!(build, "const std = @import(\"std\");")?;
+    writeln!(build)?;
+    writeln!(build, "pub fn build(b: *std.Build) void {{")?;
+    writeln!(
+        build,
+        "    const optimize = b.standardOptimizeOption(.{{}});"
+    )?;
+    writeln!(build, "    const target = b.standardTargetOptions(.{{}});")?;
+
+    // Declared up front, so components can link each other in any order.
+    for (index, component) in components.iter().enumerate() {
+        let function = match component.kind {
+            Kind::Static => "addStaticLibrary",
+            Kind::Shared => "addSharedLibrary",
+            Kind::Executable => "addExecutable",
+        };
+
+        writeln!(build)?;
+        writeln!(build, "    const c{index} = b.{function}(.{{")?;
+        writeln!(build, "        .name = {},", string(&component.name))?;
+        writeln!(build, "        .target = target,")?;
+        writeln!(build, "        .optimize = optimize,")?;
+        writeln!(build, "    }});")?;
+    }
+
+    for (index, component) in components.iter().enumerate() {
+        let flags = component
+            .defines
+            .iter()
+            .map(|define| format!("-D{define}"))
+            .chain(component.cflags.iter().cloned())
+            .map(|flag| format!(" {},", string(&flag)))
+            .collect::<String>();
+
+        writeln!(build)?;
+        writeln!(
+            build,
+            "    const c{index}_flags = &[_][]const u8{{{flags}}};"
+        )?;
+
+        for source in &component.sources {
+            writeln!(
+                build,
+                "    c{index}.addCSourceFile(.{{ .file = .{{ .path = {} }}, .flags = c{index}_flags }});",
+                string(source)
+            )?;
+        }
+
+        for include_dir in &component.include_dirs {
+            writeln!(
+                build,
+                "    c{index}.addIncludePath(.{{ .path = {} }});",
+                string(include_dir)
+            )?;
+        }
+
+        match component.libc {
+            Libc::None => {}
+            Libc::C => writeln!(build, "    c{index}.linkLibC();")?,
+            Libc::Cxx => writeln!(build, "    c{index}.linkLibCpp();")?,
+        }
+
+        for link in &component.links {
+            match libraries.get(link.as_str()) {
+                Some(library) if *library != index => {
+                    writeln!(build, "    c{index}.linkLibrary(c{library});")?
+                }
+                _ => writeln!(build, "    c{index}.linkSystemLibrary({});", string(link))?,
+            }
+        }
+
+        writeln!(build, "    b.installArtifact(c{index});")?;
+    }
+
+    writeln!(build, "}}")?;
+
+    Ok(())
+}
+
+/// Quote `string` as a zig string literal.
+///
+/// Rust's escapes, `\n`, `\"` or `\u{..}`, are valid in zig, except for
+/// `\0`, which is written as `\x00`.
+fn string(string: &str) -> String {
+    let mut quoted = String::from("\"");
+
+    for character in string.chars() {
+        match character {
+            '\0' => quoted.push_str("\\x00"),
+            character => quoted.extend(character.escape_debug()),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn component(name: &str, kind: Kind) -> Component {
+        Component {
+            name: name.into(),
+            kind,
+            sources: vec![format!("{name}.c")],
+            include_dirs: Vec::new(),
+            defines: Vec::new(),
+            cflags: Vec::new(),
+            links: Vec::new(),
+            libc: Libc::C,
+        }
+    }
+
+    #[test]
+    fn render_kinds() {
+        let build = render(&[
+            component("a", Kind::Static),
+            component("b", Kind::Shared),
+            component("c", Kind::Executable),
+        ]);
+
+        assert!(build.contains("    const c0 = b.addStaticLibrary(.{\n        .name = \"a\","));
+        assert!(build.contains("    const c1 = b.addSharedLibrary(.{\n        .name = \"b\","));
+        assert!(build.contains("    const c2 = b.addExecutable(.{\n        .name = \"c\","));
+
+        for index in 0..3 {
+            assert!(build.contains(&format!("    b.installArtifact(c{index});")));
+        }
+    }
+
+    #[test]
+    fn render_sources_and_flags() {
+        let build = render(&[Component {
+            include_dirs: vec![String::from("include")],
+            defines: vec![String::from("NDEBUG"), String::from("LEVEL=2")],
+            cflags: vec![String::from("-O3")],
+            ..component("a", Kind::Static)
+        }]);
+
+        assert!(build.contains(
+            "    const c0_flags = &[_][]const u8{ \"-DNDEBUG\", \"-DLEVEL=2\", \"-O3\",};"
+        ));
+        assert!(build.contains(
+            "    c0.addCSourceFile(.{ .file = .{ .path = \"a.c\" }, .flags = c0_flags });"
+        ));
+        assert!(build.contains("    c0.addIncludePath(.{ .path = \"include\" });"));
+    }
+
+    #[test]
+    fn render_empty_flags() {
+        let build = render(&[component("a", Kind::Static)]);
+
+        assert!(build.contains("    const c0_flags = &[_][]const u8{};"));
+    }
+
+    #[test]
+    fn render_links() {
+        let build = render(&[
+            component("a", Kind::Static),
+            Component {
+                links: vec![String::from("a"), String::from("m"), String::from("c")],
+                ..component("c", Kind::Executable)
+            },
+        ]);
+
+        assert!(build.contains("    c1.linkLibrary(c0);"));
+        assert!(build.contains("    c1.linkSystemLibrary(\"m\");"));
+        // Executables are not libraries, a link to one is to the system.
+        assert!(build.contains("    c1.linkSystemLibrary(\"c\");"));
+    }
+
+    #[test]
+    fn render_self_link() {
+        let build = render(&[Component {
+            links: vec![String::from("z")],
+            ..component("z", Kind::Shared)
+        }]);
+
+        assert!(build.contains("    c0.linkSystemLibrary(\"z\");"));
+        assert!(!build.contains("linkLibrary(c0)"));
+    }
+
+    #[test]
+    fn render_libc() {
+        let build = render(&[
+            Component {
+                libc: Libc::None,
+                ..component("a", Kind::Static)
+            },
+            component("b", Kind::Static),
+            Component {
+                libc: Libc::Cxx,
+                ..component("c", Kind::Static)
+            },
+        ]);
+
+        assert!(!build.contains("c0.linkLibC"));
+        assert!(!build.contains("c0.linkLibCpp"));
+        assert!(build.contains("    c1.linkLibC();"));
+        assert!(build.contains("    c2.linkLibCpp();"));
+    }
+
+    #[test]
+    fn string_escapes() {
+        assert_eq!(string("a\"b\\c\n"), "\"a\\\"b\\\\c\\n\"");
+        assert_eq!(string("\0"), "\"\\x00\"");
+        assert_eq!(string("\\0"), "\"\\\\0\"");
+    }
+
+    #[test]
+    fn beta_artifacts() {
+        let components = from_beta_artifacts(&[
+            (String::from("lib zstd"), vec![String::from("lib/*.c")]),
+            (String::from("bin zstd"), vec![String::from("programs/*.c")]),
+            (String::from("unknown x"), Vec::new()),
+        ]);
+
+        assert_eq!(components.len(), 2);
+
+        assert_eq!(components[0].kind, Kind::Static);
+        assert_eq!(components[0].name, "zstd");
+        assert!(components[0].links.is_empty());
+
+        assert_eq!(components[1].kind, Kind::Executable);
+        assert_eq!(components[1].sources, ["programs/*.c"]);
+        assert_eq!(components[1].links, ["zstd"]);
+        assert_eq!(components[1].include_dirs, ["lib", "lib/common"]);
+    }
+
+    #[test]
+    fn expand_globs() {
+        let dir = Utf8PathBuf::try_from(std::env::temp_dir())
+            .unwrap()
+            .join(format!("milk-zig-{}", std::process::id()));
+
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+
+        for file in ["src/a.c", "src/b.c", "src/c.h"] {
+            std::fs::write(dir.join(file), "").unwrap();
+        }
+
+        let expanded = expand(
+            &[Component {
+                sources: vec![String::from("src/*.c")],
+                ..component("a", Kind::Static)
+            }],
+            &dir,
+        );
+
+        let missing = expand(
+            &[Component {
+                sources: vec![String::from("missing/*.c")],
+                ..component("a", Kind::Static)
+            }],
+            &dir,
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(expanded.unwrap()[0].sources, ["src/a.c", "src/b.c"]);
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+}